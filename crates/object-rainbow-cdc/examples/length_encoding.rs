@@ -0,0 +1,30 @@
+use std::time::{Duration, Instant};
+
+use object_rainbow::Size;
+use object_rainbow_cdc::{Chunk, LegacyChunk};
+use rand::random_iter;
+use static_assertions::const_assert_eq;
+
+// the length takes `u32` instead of `u16`, with the upper half no longer in the hash
+const_assert_eq!(Chunk::SIZE, LegacyChunk::SIZE + 2);
+
+fn time<T>(f: impl FnOnce() -> object_rainbow::Result<T>) -> object_rainbow::Result<Duration> {
+    let start = Instant::now();
+    f()?;
+    Ok(start.elapsed())
+}
+
+fn main() -> object_rainbow::Result<()> {
+    for shift in [16, 18, 20, 22, 24, 26] {
+        let data = random_iter().take(1 << shift).collect::<Vec<u8>>();
+        let mut legacy = Duration::ZERO;
+        let mut current = Duration::ZERO;
+        for n in 0..16u8 {
+            let data = [data.as_slice(), &[n]].concat();
+            legacy += time(|| LegacyChunk::new(&data))?;
+            current += time(|| Chunk::new(&data))?;
+        }
+        assert!(current < legacy, "{} bytes", data.len() + 1);
+    }
+    Ok(())
+}
@@ -0,0 +1,374 @@
+use std::pin::pin;
+
+use fastcdc::v2020::{MASKS, Normalization, cut_gear, get_gear_with_seed};
+use futures_util::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, Stream, StreamExt, TryStreamExt, io::BufReader,
+};
+use genawaiter_try_stream::try_stream;
+use object_rainbow::{
+    Enum, InlineOutput, ListHashes, Parse, ParseInline, Tagged, ToOutput, Topological,
+};
+
+/// Splits a byte source into `(offset, data)` chunks.
+pub trait Chunker {
+    fn bytes_stream(
+        self,
+        source: impl Send + AsyncRead,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(u64, Vec<u8>)>>;
+}
+
+/// Which [`Chunker`] was used, along with its parameters. Recorded in [`Chunks`].
+///
+/// [`Chunks`]: crate::Chunks
+#[derive(
+    Enum,
+    ToOutput,
+    InlineOutput,
+    Tagged,
+    ListHashes,
+    Topological,
+    Parse,
+    ParseInline,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub enum ChunkerConfig {
+    FastCdc(FastCdc),
+    FixedSize(FixedSize),
+    Buzhash(Buzhash),
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self::FastCdc(Default::default())
+    }
+}
+
+impl Chunker for ChunkerConfig {
+    fn bytes_stream(
+        self,
+        source: impl Send + AsyncRead,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(u64, Vec<u8>)>> {
+        try_stream(async move |co| {
+            let mut stream = match self {
+                Self::FastCdc(chunker) => chunker.bytes_stream(source).left_stream(),
+                Self::FixedSize(chunker) => {
+                    chunker.bytes_stream(source).left_stream().right_stream()
+                }
+                Self::Buzhash(chunker) => {
+                    chunker.bytes_stream(source).right_stream().right_stream()
+                }
+            };
+            while let Some(chunk) = stream.try_next().await? {
+                co.yield_(chunk).await;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// FastCDC (2020) with explicit size bounds and normalization level.
+///
+/// Cuts are the same as [`fastcdc`]'s [`AsyncStreamCDC`](fastcdc::v2020::AsyncStreamCDC) makes,
+/// but sizes aren't held to its limits, which the default, kept from before chunkers were
+/// configurable, goes past. Only as much of the source is buffered as it takes to find a cut.
+#[derive(
+    ToOutput,
+    InlineOutput,
+    Tagged,
+    ListHashes,
+    Topological,
+    Parse,
+    ParseInline,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub struct FastCdc {
+    pub min_size: u32,
+    pub avg_size: u32,
+    pub max_size: u32,
+    /// `0..=3`, see [`Normalization`].
+    pub level: u8,
+    /// Gear table seed. `0` means the unmodified table.
+    pub seed: u64,
+}
+
+impl Default for FastCdc {
+    fn default() -> Self {
+        Self {
+            min_size: 0x_00_01_00_00,
+            avg_size: 0x_01_00_00_00,
+            max_size: 0x_ff_ff_ff_ff,
+            level: 1,
+            seed: 0,
+        }
+    }
+}
+
+/// How much more to read at least, when there's no cut in what's buffered yet.
+const READ_SIZE: usize = 0x_00_01_00_00;
+
+impl FastCdc {
+    /// Small and large masks, see [`cut_gear`].
+    fn masks(&self) -> object_rainbow::Result<(u64, u64)> {
+        let level = match self.level {
+            0 => Normalization::Level0,
+            1 => Normalization::Level1,
+            2 => Normalization::Level2,
+            3 => Normalization::Level3,
+            _ => {
+                return Err(object_rainbow::error_operation!(
+                    "unknown normalization level"
+                ));
+            }
+        };
+        if self.avg_size == 0 || self.min_size > self.avg_size || self.avg_size > self.max_size {
+            return Err(object_rainbow::error_operation!(
+                "chunk sizes must satisfy min <= avg <= max and avg > 0"
+            ));
+        }
+        let bits = self.avg_size.ilog2();
+        if bits < level.bits() || (bits + level.bits()) as usize >= MASKS.len() {
+            return Err(object_rainbow::error_operation!(
+                "average chunk size out of range for this normalization level"
+            ));
+        }
+        Ok((
+            MASKS[(bits + level.bits()) as usize],
+            MASKS[(bits - level.bits()) as usize],
+        ))
+    }
+}
+
+impl Chunker for FastCdc {
+    fn bytes_stream(
+        self,
+        source: impl Send + AsyncRead,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(u64, Vec<u8>)>> {
+        try_stream(async move |co| {
+            let (mask_s, mask_l) = self.masks()?;
+            let (gear, gear_ls) = get_gear_with_seed(self.seed);
+            let min_size = self.min_size as usize;
+            let avg_size = self.avg_size as usize;
+            let max_size = self.max_size as usize;
+            let mut source = pin!(source);
+            let mut buffer = Vec::new();
+            let mut eof = false;
+            let mut offset = 0;
+            loop {
+                let (_, count) = cut_gear(
+                    &buffer,
+                    min_size,
+                    avg_size,
+                    max_size,
+                    mask_s,
+                    mask_l,
+                    mask_s << 1,
+                    mask_l << 1,
+                    &gear,
+                    &gear_ls,
+                );
+                // once `avg_size` is buffered, more data doesn't move a cut that's been found
+                if eof
+                    || buffer.len() >= max_size
+                    || (buffer.len() >= avg_size && count < buffer.len())
+                {
+                    if count == 0 {
+                        break;
+                    }
+                    let rest = buffer.split_off(count);
+                    co.yield_((offset, std::mem::replace(&mut buffer, rest)))
+                        .await;
+                    offset += count as u64;
+                    continue;
+                }
+                let len = buffer.len();
+                buffer.resize(len + len.max(READ_SIZE).min(max_size - len), 0);
+                let read = source.read(&mut buffer[len..]).await?;
+                buffer.truncate(len + read);
+                eof = read == 0;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Every chunk except the last one is exactly `size` bytes.
+#[derive(
+    ToOutput,
+    InlineOutput,
+    Tagged,
+    ListHashes,
+    Topological,
+    Parse,
+    ParseInline,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub struct FixedSize {
+    pub size: u32,
+}
+
+impl Default for FixedSize {
+    fn default() -> Self {
+        Self {
+            size: 0x_00_10_00_00,
+        }
+    }
+}
+
+impl Chunker for FixedSize {
+    fn bytes_stream(
+        self,
+        source: impl Send + AsyncRead,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(u64, Vec<u8>)>> {
+        try_stream(async move |co| {
+            if self.size == 0 {
+                return Err(object_rainbow::error_operation!("zero chunk size"));
+            }
+            let mut source = pin!(source);
+            let mut offset = 0;
+            loop {
+                let mut chunk = Vec::new();
+                (&mut source)
+                    .take(self.size.into())
+                    .read_to_end(&mut chunk)
+                    .await?;
+                if chunk.is_empty() {
+                    break;
+                }
+                let len = chunk.len() as u64;
+                co.yield_((offset, chunk)).await;
+                offset += len;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Cyclic polynomial (buzhash) rolling hash over a fixed window. A chunk ends once it's at least
+/// `min_size` long and the low bits of the hash are all zero, or once it reaches `max_size`.
+#[derive(
+    ToOutput,
+    InlineOutput,
+    Tagged,
+    ListHashes,
+    Topological,
+    Parse,
+    ParseInline,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub struct Buzhash {
+    pub min_size: u32,
+    /// Rounded down to a power of two. Expected chunk size is roughly `min_size + avg_size`.
+    pub avg_size: u32,
+    pub max_size: u32,
+    pub window: u32,
+}
+
+impl Default for Buzhash {
+    fn default() -> Self {
+        Self {
+            min_size: 0x_00_01_00_00,
+            avg_size: 0x_00_10_00_00,
+            max_size: 0x_01_00_00_00,
+            window: 64,
+        }
+    }
+}
+
+const BUZHASH_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        // splitmix64
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        table[i] = (z >> 32) as u32;
+        i += 1;
+    }
+    table
+};
+
+impl Buzhash {
+    fn mask(&self) -> object_rainbow::Result<u32> {
+        if self.avg_size == 0 || self.window == 0 {
+            return Err(object_rainbow::error_operation!(
+                "zero average or window size"
+            ));
+        }
+        if self.min_size > self.max_size || self.max_size == 0 {
+            return Err(object_rainbow::error_operation!(
+                "chunk sizes must satisfy min <= max and max > 0"
+            ));
+        }
+        Ok((1 << self.avg_size.ilog2()) - 1)
+    }
+}
+
+impl Chunker for Buzhash {
+    fn bytes_stream(
+        self,
+        source: impl Send + AsyncRead,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(u64, Vec<u8>)>> {
+        try_stream(async move |co| {
+            let mask = self.mask()?;
+            let min_size = self.min_size as usize;
+            let max_size = self.max_size as usize;
+            let window = self.window as usize;
+            let mut source = pin!(BufReader::new(source));
+            let mut offset = 0;
+            let mut chunk = Vec::new();
+            let mut hash = 0u32;
+            loop {
+                let buf = source.fill_buf().await?;
+                if buf.is_empty() {
+                    break;
+                }
+                let mut consumed = 0;
+                let mut cut = false;
+                for &byte in buf {
+                    consumed += 1;
+                    chunk.push(byte);
+                    hash = hash.rotate_left(1) ^ BUZHASH_TABLE[byte as usize];
+                    if chunk.len() > window {
+                        let out = chunk[chunk.len() - 1 - window];
+                        hash ^= BUZHASH_TABLE[out as usize].rotate_left(self.window);
+                    }
+                    if chunk.len() >= max_size || (chunk.len() >= min_size && hash & mask == 0) {
+                        cut = true;
+                        break;
+                    }
+                }
+                source.consume_unpin(consumed);
+                if cut {
+                    let len = chunk.len() as u64;
+                    co.yield_((offset, std::mem::take(&mut chunk))).await;
+                    offset += len;
+                    hash = 0;
+                }
+            }
+            if !chunk.is_empty() {
+                co.yield_((offset, chunk)).await;
+            }
+            Ok(())
+        })
+    }
+}
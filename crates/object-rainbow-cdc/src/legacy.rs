@@ -0,0 +1,238 @@
+use object_rainbow::{
+    DiffHashes, Fetch, Hash, InlineOutput, ListHashes, Parse, ParseInline, Singular, Size, SizeExt,
+    Tagged, ToOutput, Topological,
+};
+use object_rainbow_point::{IntoPoint, Point};
+use sha2::{Digest, Sha256};
+use static_assertions::const_assert_eq;
+
+use crate::{Chunk, ChunkerConfig, Chunks};
+
+/// Original [`Chunk`] encoding: the upper bits of the length are encoded in the data hash by
+/// brute-forcing a tail (see [`generate_tail`]). Kept for reading and migrating existing data.
+#[derive(
+    ToOutput, InlineOutput, Tagged, ListHashes, Topological, Parse, ParseInline, Size, Clone, Debug,
+)]
+pub struct LegacyChunk {
+    len_lower: u16,
+    data: Point<Vec<u8>>,
+}
+
+impl Default for LegacyChunk {
+    fn default() -> Self {
+        Self::new(&[]).unwrap()
+    }
+}
+
+impl LegacyChunk {
+    pub async fn data(&self) -> object_rainbow::Result<Vec<u8>> {
+        let len = self.len()?;
+        let mut data = self.data.fetch().await?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    pub async fn into_data(mut self) -> object_rainbow::Result<Vec<u8>> {
+        let len = self.len()?;
+        let mut data = self.data.fetch_take().await?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    pub fn new(data: &[u8]) -> object_rainbow::Result<Self> {
+        let tail = generate_tail(data)?;
+        let len_lower = (data.len() % 65536) as u16;
+        let data = [data, tail.as_slice()].concat().point();
+        Ok(Self { len_lower, data })
+    }
+
+    pub fn len(&self) -> object_rainbow::Result<usize> {
+        let len = u64::from(self.len_lower)
+            + (u64::from(derive_length_from_hash(self.data.hash())) << 16);
+        let len = len
+            .try_into()
+            .map_err(|_| object_rainbow::Error::UnsupportedLength)?;
+        Ok(len)
+    }
+
+    pub fn is_empty(&self) -> object_rainbow::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Re-encode as [`Chunk`]. The data gets fetched and pointed to again, without the tail.
+    pub async fn into_chunk(self) -> object_rainbow::Result<Chunk> {
+        Chunk::new(&self.into_data().await?)
+    }
+}
+
+/// Original [`Chunks`] encoding: every [`LegacyChunk`] inline, one after another.
+#[derive(ToOutput, Tagged, ListHashes, Topological, Parse, Clone, Debug, Default)]
+pub struct LegacyChunks {
+    chunks: Vec<LegacyChunk>,
+}
+
+impl LegacyChunks {
+    pub fn new(chunks: Vec<LegacyChunk>) -> Self {
+        Self { chunks }
+    }
+
+    pub fn len(&self) -> object_rainbow::Result<usize> {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> object_rainbow::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    pub fn chunk_len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Re-encode every chunk with [`LegacyChunk::into_chunk`], keeping the boundaries. `config`
+    /// is only recorded. The legacy chunker is [`ChunkerConfig::default`].
+    pub async fn into_chunks(self, config: ChunkerConfig) -> object_rainbow::Result<Chunks> {
+        let chunks = futures_util::future::try_join_all(
+            self.chunks.into_iter().map(LegacyChunk::into_chunk),
+        )
+        .await?;
+        Chunks::from_chunks(config, chunks)
+    }
+}
+
+pub fn generate_tail(data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+    let diff = DiffHashes::default().data_hash();
+    let mut hasher = Sha256::new();
+    hasher.update(diff);
+    hasher.update(data);
+    let hasher = hasher;
+    let target = data.len() >> 16;
+    let target: u32 = target
+        .try_into()
+        .map_err(|_| object_rainbow::Error::UnsupportedLength)?;
+    for len in 0..=16 {
+        for tail in 0u128..(1 << (len * 8)) {
+            let tail = tail.to_be_bytes()[(16 - len)..].to_vec();
+            let mut hasher = hasher.clone();
+            hasher.update(&tail);
+            if derive_length_from_hash(Hash::from_hasher(hasher)) == target {
+                return Ok(tail);
+            }
+        }
+    }
+    Err(object_rainbow::error_operation!(
+        "couldn't find tail in 16 bytes or less"
+    ))
+}
+
+pub fn derive_length_from_hash(hash: Hash) -> u32 {
+    derive_length(hash.reinterpret::<(u64, u64, u64, u64)>().0)
+}
+
+fn derive_length(source: u64) -> u32 {
+    const SIZE: u32 = u64::BITS;
+    const HEAD_SIZE: u32 = 4;
+    const TAIL_SIZE: u32 = SIZE - HEAD_SIZE;
+    const TAIL_MASK: u64 = (1 << TAIL_SIZE) - 1;
+    const BASE_BITS: u32 = 1;
+    const MAX_BITS: u32 = BASE_BITS + ((1 << HEAD_SIZE) - 1) - 1;
+    const MAX_GARBAGE: u32 = TAIL_SIZE - BASE_BITS;
+    const_assert_eq!(MAX_BITS, 15);
+    let head = (source >> TAIL_SIZE) as u32;
+    let tail = source & TAIL_MASK;
+    let garbage = MAX_GARBAGE - head.saturating_sub(1);
+    let extra = (tail >> garbage) as u32;
+    let main = if head == 0 {
+        0
+    } else {
+        1 << (BASE_BITS - 1 + head)
+    };
+    main | extra
+}
+
+#[test]
+#[expect(clippy::unusual_byte_groupings)]
+fn cases() {
+    let f = derive_length;
+    assert_eq!(
+        f(0b_0000_0_11111111111111111111111111111111111111111111111111111111111),
+        0b_0,
+    );
+    assert_eq!(
+        f(0b_0000_1_00000000000000000000000000000000000000000000000000000000000),
+        0b_1,
+    );
+    assert_eq!(
+        f(0b_0001_0_11111111111111111111111111111111111111111111111111111111111),
+        0b_10,
+    );
+    assert_eq!(
+        f(0b_0001_1_00000000000000000000000000000000000000000000000000000000000),
+        0b_11,
+    );
+    assert_eq!(
+        f(0b_0010_00_1111111111111111111111111111111111111111111111111111111111),
+        0b_100,
+    );
+    assert_eq!(
+        f(0b_0010_11_0000000000000000000000000000000000000000000000000000000000),
+        0b_111,
+    );
+    assert_eq!(
+        f(0b_0100_0000_11111111111111111111111111111111111111111111111111111111),
+        0b_10000,
+    );
+    assert_eq!(
+        f(0b_0100_1111_00000000000000000000000000000000000000000000000000000000),
+        0b_11111,
+    );
+    assert_eq!(
+        f(0b_1000_00000000_1111111111111111111111111111111111111111111111111111),
+        0b_100000000,
+    );
+    assert_eq!(
+        f(0b_1000_11111111_0000000000000000000000000000000000000000000000000000),
+        0b_111111111,
+    );
+    assert_eq!(
+        f(0b_1111_000000000000000_111111111111111111111111111111111111111111111),
+        0b_1000000000000000,
+    );
+    assert_eq!(
+        f(0b_1111_111111111111111_000000000000000000000000000000000000000000000),
+        0b_1111111111111111,
+    );
+}
+
+#[test]
+fn migrate() -> object_rainbow::Result<()> {
+    let original = b"325074".repeat(1000);
+    let legacy = LegacyChunk::new(&original)?;
+    assert_eq!(legacy.len()?, original.len());
+    let chunk = smol::block_on(legacy.into_chunk())?;
+    assert_eq!(chunk.len(), original.len());
+    assert_eq!(smol::block_on(chunk.data())?, original);
+    Ok(())
+}
+
+#[test]
+fn migrate_chunks() -> object_rainbow::Result<()> {
+    use object_rainbow::ParseSlice;
+
+    let parts = [b"abc".repeat(100), Vec::new(), b"defg".repeat(30000)];
+    let legacy = LegacyChunks::new(
+        parts
+            .iter()
+            .map(|part| LegacyChunk::new(part))
+            .collect::<object_rainbow::Result<_>>()?,
+    )
+    .reparse()?;
+    assert_eq!(legacy.chunk_len(), 3);
+    assert_eq!(legacy.len()?, parts.concat().len());
+    let chunks = smol::block_on(legacy.into_chunks(ChunkerConfig::default()))?;
+    assert_eq!(chunks.chunk_len(), 3);
+    for (index, part) in parts.iter().enumerate() {
+        let chunk = smol::block_on(chunks.get(index as u64))?.unwrap();
+        assert_eq!(smol::block_on(chunk.data())?, *part);
+    }
+    Ok(())
+}
@@ -1,58 +1,172 @@
 use std::pin::pin;
 
-use fastcdc::v2020::{AsyncStreamCDC, Normalization};
-use futures_util::{AsyncRead, Stream, StreamExt, TryStreamExt};
+use futures_util::{AsyncRead, Stream, TryStreamExt};
 use genawaiter_try_stream::try_stream;
-use object_rainbow::{DiffHashes, Fetch, Hash, InlineOutput, Singular, SizeExt, ToOutput};
+use object_rainbow::{
+    Fetch, InlineOutput, ListHashes, Parse, ParseInline, Size, Tagged, ToOutput, Topological,
+};
+use object_rainbow_append_tree::AppendTree;
 use object_rainbow_point::{IntoPoint, Point};
-use sha2::{Digest, Sha256};
-use static_assertions::const_assert_eq;
 
-#[derive(ToOutput)]
+pub use self::{
+    chunker::{Buzhash, Chunker, ChunkerConfig, FastCdc, FixedSize},
+    legacy::{LegacyChunk, LegacyChunks, derive_length_from_hash, generate_tail},
+    reader::ChunksReader,
+};
+
+mod chunker;
+mod legacy;
+mod reader;
+
+#[derive(
+    ToOutput, InlineOutput, Tagged, ListHashes, Topological, Parse, ParseInline, Clone, Debug,
+)]
+struct Entry {
+    end: u64,
+    chunk: Chunk,
+}
+
+#[derive(
+    ToOutput, InlineOutput, Tagged, ListHashes, Topological, Parse, ParseInline, Clone, Default,
+)]
 pub struct Chunks {
-    chunks: Vec<Chunk>,
+    config: ChunkerConfig,
+    chunks: AppendTree<Entry>,
 }
 
 impl Chunks {
+    pub const fn new(config: ChunkerConfig) -> Self {
+        Self {
+            config,
+            chunks: AppendTree::new(),
+        }
+    }
+
+    pub fn config(&self) -> ChunkerConfig {
+        self.config
+    }
+
     pub fn bytes_stream(
+        &self,
         source: impl Send + AsyncRead,
     ) -> impl Send + Stream<Item = object_rainbow::Result<(u64, Vec<u8>)>> {
-        try_stream(async move |co| {
-            let source = pin!(source);
-            let mut stream = AsyncStreamCDC::with_level(
-                source,
-                0x_00_01_00_00,
-                0x_01_00_00_00,
-                0x_ff_ff_ff_ff,
-                Normalization::Level1,
-            );
-            stream
-                .as_stream()
-                .map_ok(|chunk| (chunk.offset, chunk.data))
-                .try_for_each(|chunk| async {
-                    co.yield_(chunk).await;
-                    Ok(())
-                })
-                .await
-                .map_err(std::io::Error::from)?;
-            Ok(())
-        })
+        self.config.bytes_stream(source)
     }
 
     pub async fn in_memory<F: Future<Output = object_rainbow::Result<Chunk>>>(
+        config: ChunkerConfig,
         source: impl Send + AsyncRead,
         mut schedule: impl FnMut(Box<dyn Send + FnOnce() -> object_rainbow::Result<Chunk>>) -> F,
     ) -> object_rainbow::Result<Self> {
-        let chunks = Self::bytes_stream(source)
+        let chunks = config
+            .bytes_stream(source)
             .map_ok(|(_, chunk)| schedule(Box::new(move || Chunk::new(&chunk))))
             .try_collect::<Vec<_>>()
             .await?;
         let chunks = futures_util::future::try_join_all(chunks).await?;
-        Ok(Self { chunks })
+        Self::from_chunks(config, chunks)
+    }
+
+    pub fn from_chunks(
+        config: ChunkerConfig,
+        chunks: impl IntoIterator<Item = Chunk>,
+    ) -> object_rainbow::Result<Self> {
+        let mut this = Self::new(config);
+        for chunk in chunks {
+            this.push(chunk)?;
+        }
+        Ok(this)
+    }
+
+    pub fn push(&mut self, chunk: Chunk) -> object_rainbow::Result<()> {
+        let end = self
+            .end()
+            .checked_add(chunk.len() as u64)
+            .ok_or(object_rainbow::Error::UnsupportedLength)?;
+        self.chunks.push(Entry { end, chunk })?;
+        Ok(())
+    }
+
+    /// One lookup, so only the first chunk's offset gets checked. Callers going through
+    /// consecutive chunks check that each one starts where the previous one ends.
+    async fn entry(&self, index: u64) -> object_rainbow::Result<Option<(u64, Chunk)>> {
+        let Some(Entry { end, chunk }) = self.chunks.get(index).await? else {
+            return Ok(None);
+        };
+        let start = end
+            .checked_sub(chunk.len() as u64)
+            .ok_or_else(|| object_rainbow::error_consistency!("chunk ends before it starts"))?;
+        if index == 0 && start != 0 {
+            return Err(object_rainbow::error_consistency!(
+                "first chunk offset mismatch"
+            ));
+        }
+        Ok(Some((start, chunk)))
+    }
+
+    pub async fn get(&self, index: u64) -> object_rainbow::Result<Option<Chunk>> {
+        Ok(self.entry(index).await?.map(|(_, chunk)| chunk))
+    }
+
+    async fn locate(&self, offset: u64) -> object_rainbow::Result<Option<u64>> {
+        if offset >= self.end() {
+            return Ok(None);
+        }
+        let mut lo = 0;
+        let mut hi = self.chunk_len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let Entry { end, .. } = self
+                .chunks
+                .get(mid)
+                .await?
+                .ok_or_else(|| object_rainbow::error_consistency!("missing chunk"))?;
+            if end > offset {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        Ok(Some(lo))
+    }
+
+    pub async fn chunk_at(&self, offset: u64) -> object_rainbow::Result<Option<(u64, Chunk)>> {
+        let Some(index) = self.locate(offset).await? else {
+            return Ok(None);
+        };
+        let entry = self.entry(index).await?;
+        if let Some((start, _)) = &entry
+            && *start > offset
+        {
+            return Err(object_rainbow::error_consistency!("chunk offset mismatch"));
+        }
+        Ok(entry)
+    }
+
+    pub fn into_reader(self) -> ChunksReader {
+        ChunksReader::new(self)
+    }
+
+    pub fn chunks(&self) -> impl '_ + Send + Stream<Item = object_rainbow::Result<Chunk>> {
+        try_stream(async move |co| {
+            let mut end = 0;
+            for index in 0..self.chunk_len() {
+                let (start, chunk) = self
+                    .entry(index)
+                    .await?
+                    .ok_or_else(|| object_rainbow::error_consistency!("missing chunk"))?;
+                if start != end {
+                    return Err(object_rainbow::error_consistency!("chunk offset mismatch"));
+                }
+                end = start + chunk.len() as u64;
+                co.yield_(chunk).await;
+            }
+            Ok(())
+        })
     }
 
     pub fn as_stream(&self) -> impl '_ + Send + Stream<Item = object_rainbow::Result<Vec<u8>>> {
-        futures_util::stream::iter(&self.chunks).then(|chunk| chunk.data())
+        self.chunks().and_then(Chunk::into_data)
     }
 
     pub fn as_async_read(&self) -> impl '_ + Send + AsyncRead {
@@ -60,29 +174,43 @@ impl Chunks {
     }
 
     pub fn into_stream(self) -> impl Send + Stream<Item = object_rainbow::Result<Vec<u8>>> {
-        futures_util::stream::iter(self.chunks).then(|chunk| chunk.into_data())
+        try_stream(async move |co| {
+            let mut chunks = pin!(self.as_stream());
+            while let Some(data) = chunks.try_next().await? {
+                co.yield_(data).await;
+            }
+            Ok(())
+        })
     }
 
     pub fn into_async_read(self) -> impl Send + AsyncRead {
         self.into_stream().map_err(|e| e.into()).into_async_read()
     }
 
+    fn end(&self) -> u64 {
+        self.chunks.last().map_or(0, |entry| entry.end)
+    }
+
     pub fn len(&self) -> object_rainbow::Result<usize> {
-        self.chunks.iter().map(|chunk| chunk.len()).sum()
+        self.end()
+            .try_into()
+            .map_err(|_| object_rainbow::Error::UnsupportedLength)
     }
 
     pub fn is_empty(&self) -> object_rainbow::Result<bool> {
         Ok(self.len()? == 0)
     }
 
-    pub fn chunk_len(&self) -> usize {
+    pub fn chunk_len(&self) -> u64 {
         self.chunks.len()
     }
 }
 
-#[derive(ToOutput, InlineOutput)]
+#[derive(
+    ToOutput, InlineOutput, Tagged, ListHashes, Topological, Parse, ParseInline, Size, Clone, Debug,
+)]
 pub struct Chunk {
-    len_lower: u16,
+    len: u32,
     data: Point<Vec<u8>>,
 }
 
@@ -93,141 +221,215 @@ impl Default for Chunk {
 }
 
 impl Chunk {
+    fn check(&self, data: &[u8]) -> object_rainbow::Result<()> {
+        if data.len() != self.len() {
+            return Err(object_rainbow::error_consistency!("chunk length mismatch"));
+        }
+        Ok(())
+    }
+
     pub async fn data(&self) -> object_rainbow::Result<Vec<u8>> {
-        let len = self.len()?;
-        let mut data = self.data.fetch().await?;
-        data.truncate(len);
+        let data = self.data.fetch().await?;
+        self.check(&data)?;
         Ok(data)
     }
 
     pub async fn into_data(mut self) -> object_rainbow::Result<Vec<u8>> {
-        let len = self.len()?;
-        let mut data = self.data.fetch_take().await?;
-        data.truncate(len);
+        let data = self.data.fetch_take().await?;
+        self.check(&data)?;
         Ok(data)
     }
 
     pub fn new(data: &[u8]) -> object_rainbow::Result<Self> {
-        let tail = generate_tail(data)?;
-        let len_lower = (data.len() % 65536) as u16;
-        let data = [data, tail.as_slice()].concat().point();
-        Ok(Self { len_lower, data })
-    }
-
-    pub fn len(&self) -> object_rainbow::Result<usize> {
-        let len = u64::from(self.len_lower)
-            + (u64::from(derive_length_from_hash(self.data.hash())) << 16);
-        let len = len
+        let len = data
+            .len()
             .try_into()
             .map_err(|_| object_rainbow::Error::UnsupportedLength)?;
-        Ok(len)
+        let data = data.to_vec().point();
+        Ok(Self { len, data })
     }
 
-    pub fn is_empty(&self) -> object_rainbow::Result<bool> {
-        Ok(self.len()? == 0)
+    pub fn len(&self) -> usize {
+        self.len as usize
     }
-}
 
-pub fn generate_tail(data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
-    let diff = DiffHashes::default().data_hash();
-    let mut hasher = Sha256::new();
-    hasher.update(diff);
-    hasher.update(data);
-    let hasher = hasher;
-    let target = data.len() >> 16;
-    let target: u32 = target
-        .try_into()
-        .map_err(|_| object_rainbow::Error::UnsupportedLength)?;
-    for len in 0..=16 {
-        for tail in 0u128..(1 << (len * 8)) {
-            let tail = tail.to_be_bytes()[(16 - len)..].to_vec();
-            let mut hasher = hasher.clone();
-            hasher.update(&tail);
-            if derive_length_from_hash(Hash::from_hasher(hasher)) == target {
-                return Ok(tail);
-            }
-        }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
-    Err(object_rainbow::error_operation!(
-        "couldn't find tail in 16 bytes or less"
-    ))
 }
 
-pub fn derive_length_from_hash(hash: Hash) -> u32 {
-    derive_length(hash.reinterpret::<(u64, u64, u64, u64)>().0)
+#[test]
+fn chunks_roundtrip() -> object_rainbow::Result<()> {
+    use futures_util::AsyncReadExt;
+    use object_rainbow::ParseSlice;
+
+    let parts = (0..300u32)
+        .map(|n| n.to_le_bytes().repeat(n as usize))
+        .collect::<Vec<_>>();
+    let chunks = Chunks::from_chunks(
+        ChunkerConfig::default(),
+        parts
+            .iter()
+            .map(|part| Chunk::new(part))
+            .collect::<object_rainbow::Result<Vec<_>>>()?,
+    )?
+    .reparse()?;
+    assert_eq!(chunks.chunk_len(), 300);
+    let expected = parts.concat();
+    assert_eq!(chunks.len()?, expected.len());
+    let mut data = Vec::new();
+    smol::block_on(chunks.into_async_read().read_to_end(&mut data))?;
+    assert_eq!(data, expected);
+    Ok(())
 }
 
-fn derive_length(source: u64) -> u32 {
-    const SIZE: u32 = u64::BITS;
-    const HEAD_SIZE: u32 = 4;
-    const TAIL_SIZE: u32 = SIZE - HEAD_SIZE;
-    const TAIL_MASK: u64 = (1 << TAIL_SIZE) - 1;
-    const BASE_BITS: u32 = 1;
-    const MAX_BITS: u32 = BASE_BITS + ((1 << HEAD_SIZE) - 1) - 1;
-    const MAX_GARBAGE: u32 = TAIL_SIZE - BASE_BITS;
-    const_assert_eq!(MAX_BITS, 15);
-    let head = (source >> TAIL_SIZE) as u32;
-    let tail = source & TAIL_MASK;
-    let garbage = MAX_GARBAGE - head.saturating_sub(1);
-    let extra = (tail >> garbage) as u32;
-    let main = if head == 0 {
-        0
-    } else {
-        1 << (BASE_BITS - 1 + head)
-    };
-    main | extra
+#[test]
+fn reader_seek() -> object_rainbow::Result<()> {
+    use std::io::SeekFrom;
+
+    use futures_util::{AsyncReadExt, AsyncSeekExt};
+
+    let parts = (0..100u32)
+        .map(|n| n.to_le_bytes().repeat((n * 7 % 13) as usize))
+        .collect::<Vec<_>>();
+    let expected = parts.concat();
+    let chunks = Chunks::from_chunks(
+        ChunkerConfig::default(),
+        parts
+            .iter()
+            .map(|part| Chunk::new(part))
+            .collect::<object_rainbow::Result<Vec<_>>>()?,
+    )?;
+    let mut reader = chunks.into_reader().with_read_ahead(2);
+    smol::block_on(async {
+        for (start, len) in [(0, 10), (17, 100), (1000, 1), (5, 500), (900, 0)] {
+            assert_eq!(reader.seek(SeekFrom::Start(start)).await?, start);
+            let mut data = vec![0; len];
+            reader.read_exact(&mut data).await?;
+            let start = start as usize;
+            assert_eq!(data, expected[start..start + len]);
+        }
+        reader.seek(SeekFrom::End(-3)).await?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        assert_eq!(data, expected[expected.len() - 3..]);
+        Ok(())
+    })
 }
 
 #[test]
-#[expect(clippy::unusual_byte_groupings)]
-fn cases() {
-    let f = derive_length;
-    assert_eq!(
-        f(0b_0000_0_11111111111111111111111111111111111111111111111111111111111),
-        0b_0,
-    );
-    assert_eq!(
-        f(0b_0000_1_00000000000000000000000000000000000000000000000000000000000),
-        0b_1,
-    );
-    assert_eq!(
-        f(0b_0001_0_11111111111111111111111111111111111111111111111111111111111),
-        0b_10,
-    );
-    assert_eq!(
-        f(0b_0001_1_00000000000000000000000000000000000000000000000000000000000),
-        0b_11,
-    );
-    assert_eq!(
-        f(0b_0010_00_1111111111111111111111111111111111111111111111111111111111),
-        0b_100,
-    );
-    assert_eq!(
-        f(0b_0010_11_0000000000000000000000000000000000000000000000000000000000),
-        0b_111,
-    );
-    assert_eq!(
-        f(0b_0100_0000_11111111111111111111111111111111111111111111111111111111),
-        0b_10000,
-    );
-    assert_eq!(
-        f(0b_0100_1111_00000000000000000000000000000000000000000000000000000000),
-        0b_11111,
-    );
-    assert_eq!(
-        f(0b_1000_00000000_1111111111111111111111111111111111111111111111111111),
-        0b_100000000,
-    );
-    assert_eq!(
-        f(0b_1000_11111111_0000000000000000000000000000000000000000000000000000),
-        0b_111111111,
-    );
-    assert_eq!(
-        f(0b_1111_000000000000000_111111111111111111111111111111111111111111111),
-        0b_1000000000000000,
+fn chunkers() -> object_rainbow::Result<()> {
+    use fastcdc::v2020::Normalization;
+    use futures_util::io::Cursor;
+    use object_rainbow::ParseSlice;
+
+    let mut state = 1u64;
+    let data = std::iter::repeat_with(|| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (state >> 56) as u8
+    })
+    .take(1 << 16)
+    .collect::<Vec<_>>();
+    let split = |config: ChunkerConfig, data: Vec<u8>| {
+        smol::block_on(
+            config
+                .bytes_stream(Cursor::new(data))
+                .try_collect::<Vec<_>>(),
+        )
+    };
+    let fixed = split(
+        ChunkerConfig::FixedSize(FixedSize { size: 1000 }),
+        data.clone(),
+    )?;
+    assert_eq!(fixed.len(), 66);
+    assert!(
+        fixed
+            .iter()
+            .all(|(offset, chunk)| offset % 1000 == 0 && (chunk.len() == 1000 || *offset == 65000))
     );
+    let config = ChunkerConfig::Buzhash(Buzhash {
+        min_size: 256,
+        avg_size: 1024,
+        max_size: 8192,
+        window: 32,
+    });
+    let original = split(config, data.clone())?;
     assert_eq!(
-        f(0b_1111_111111111111111_000000000000000000000000000000000000000000000),
-        0b_1111111111111111,
+        original
+            .iter()
+            .map(|(_, chunk)| chunk.as_slice())
+            .collect::<Vec<_>>()
+            .concat(),
+        data,
     );
+    let shifted = split(config, [b"prefix".as_slice(), &data].concat())?;
+    let shared = shifted
+        .iter()
+        .filter(|(_, chunk)| original.iter().any(|(_, other)| other == chunk))
+        .count();
+    assert!(shared + 2 >= original.len());
+    let chunks = smol::block_on(Chunks::in_memory(
+        config,
+        Cursor::new(data.clone()),
+        async |f| f(),
+    ))?
+    .reparse()?;
+    assert_eq!(chunks.config(), config);
+    assert_eq!(chunks.chunk_len(), original.len() as u64);
+    let fastcdc = FastCdc {
+        min_size: 256,
+        avg_size: 1024,
+        max_size: 8192,
+        level: 1,
+        seed: 0,
+    };
+    for (fastcdc, level) in [
+        (fastcdc, Normalization::Level1),
+        (
+            FastCdc {
+                level: 2,
+                seed: 7,
+                ..fastcdc
+            },
+            Normalization::Level2,
+        ),
+    ] {
+        let reference = smol::block_on(
+            fastcdc::v2020::AsyncStreamCDC::with_level_and_seed(
+                Cursor::new(data.clone()),
+                fastcdc.min_size as usize,
+                fastcdc.avg_size as usize,
+                fastcdc.max_size as usize,
+                level,
+                fastcdc.seed,
+            )
+            .as_stream()
+            .map_ok(|chunk| (chunk.offset, chunk.data))
+            .try_collect::<Vec<_>>(),
+        )
+        .map_err(std::io::Error::from)?;
+        assert!(reference.len() > 1);
+        assert_eq!(
+            split(ChunkerConfig::FastCdc(fastcdc), data.clone())?,
+            reference
+        );
+    }
+    for invalid in [
+        FastCdc {
+            min_size: 2048,
+            ..fastcdc
+        },
+        FastCdc {
+            avg_size: 1 << 25,
+            max_size: 1 << 26,
+            ..fastcdc
+        },
+        FastCdc {
+            level: 4,
+            ..fastcdc
+        },
+    ] {
+        assert!(split(ChunkerConfig::FastCdc(invalid), data.clone()).is_err());
+    }
+    Ok(())
 }
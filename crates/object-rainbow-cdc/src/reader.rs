@@ -0,0 +1,192 @@
+use std::{
+    collections::VecDeque,
+    io::SeekFrom,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use futures_util::{AsyncRead, AsyncSeek, future::MaybeDone};
+use object_rainbow::FailFuture;
+
+use crate::Chunks;
+
+/// A chunk's offset and data.
+type Fetched = (u64, Vec<u8>);
+
+/// [`AsyncRead`] + [`AsyncSeek`] over [`Chunks`], fetching only the chunks that get read.
+pub struct ChunksReader {
+    chunks: Arc<Chunks>,
+    position: u64,
+    read_ahead: u64,
+    /// The chunk being read, with its index.
+    current: Option<(u64, Fetched)>,
+    /// Index of the first chunk in `ahead`.
+    next: u64,
+    /// Chunks following `next`, all fetched concurrently.
+    ahead: VecDeque<MaybeDone<FailFuture<'static, Fetched>>>,
+    locating: Option<FailFuture<'static, Option<u64>>>,
+}
+
+impl ChunksReader {
+    pub fn new(chunks: Chunks) -> Self {
+        Self {
+            chunks: Arc::new(chunks),
+            position: 0,
+            read_ahead: 0,
+            current: None,
+            next: 0,
+            ahead: VecDeque::new(),
+            locating: None,
+        }
+    }
+
+    /// Keep this many chunks past the one being read in flight.
+    pub fn with_read_ahead(mut self, read_ahead: u64) -> Self {
+        self.read_ahead = read_ahead;
+        self
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn chunks(&self) -> &Chunks {
+        &self.chunks
+    }
+
+    fn fetch(&self, index: u64) -> FailFuture<'static, Fetched> {
+        let chunks = self.chunks.clone();
+        Box::pin(async move {
+            let (start, chunk) = chunks
+                .entry(index)
+                .await?
+                .ok_or_else(|| object_rainbow::error_consistency!("missing chunk"))?;
+            Ok((start, chunk.into_data().await?))
+        })
+    }
+
+    /// Start fetching chunks up to `read_ahead` past the one at `next`.
+    fn fill(&mut self) {
+        let last = self
+            .next
+            .saturating_add(self.read_ahead)
+            .min(self.chunks.chunk_len().saturating_sub(1));
+        while self.next + (self.ahead.len() as u64) <= last {
+            let index = self.next + self.ahead.len() as u64;
+            self.ahead.push_back(MaybeDone::Future(self.fetch(index)));
+        }
+    }
+
+    fn buffered(&self) -> Option<&[u8]> {
+        let (_, (start, data)) = self.current.as_ref()?;
+        let offset = self.position.checked_sub(*start)?;
+        data.get(offset as usize..).filter(|data| !data.is_empty())
+    }
+
+    /// Poll every chunk in flight, then take the first one if it's ready.
+    fn poll_ahead(&mut self, cx: &mut Context<'_>) -> Poll<object_rainbow::Result<Fetched>> {
+        for fetching in &mut self.ahead {
+            let _ = Pin::new(fetching).poll(cx);
+        }
+        let Some(front) = self.ahead.front_mut() else {
+            return Poll::Pending;
+        };
+        let Some(fetched) = Pin::new(front).take_output() else {
+            return Poll::Pending;
+        };
+        self.ahead.pop_front();
+        Poll::Ready(fetched)
+    }
+}
+
+impl AsyncRead for ChunksReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            if buf.is_empty() || this.position >= this.chunks.end() {
+                return Poll::Ready(Ok(0));
+            }
+            if let Some(data) = this.buffered() {
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                this.position += n as u64;
+                return Poll::Ready(Ok(n));
+            }
+            if let Some(locating) = &mut this.locating {
+                let index = ready!(locating.as_mut().poll(cx));
+                this.locating = None;
+                let Some(index) = index? else {
+                    return Poll::Ready(Ok(0));
+                };
+                this.current = None;
+                this.next = index;
+                this.ahead.clear();
+                this.fill();
+                continue;
+            }
+            // reading on from the current chunk, the next one is already in flight
+            let sequential = match &this.current {
+                Some((_, (start, data))) => this.position == start + data.len() as u64,
+                None => !this.ahead.is_empty(),
+            };
+            if !sequential || this.ahead.is_empty() {
+                let chunks = this.chunks.clone();
+                let position = this.position;
+                this.locating = Some(Box::pin(async move { chunks.locate(position).await }));
+                continue;
+            }
+            let (start, data) = ready!(this.poll_ahead(cx))?;
+            let end = start + data.len() as u64;
+            // empty chunks start and end where the previous one ends
+            if start > this.position || (end <= this.position && start != this.position) {
+                return Poll::Ready(Err(object_rainbow::error_consistency!(
+                    "chunk offset mismatch"
+                )
+                .into()));
+            }
+            this.current = Some((this.next, (start, data)));
+            this.next += 1;
+            this.fill();
+        }
+    }
+}
+
+impl AsyncSeek for ChunksReader {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<std::io::Result<u64>> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.chunks.end().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                if position != self.position {
+                    self.locating = None;
+                    // keep what's in flight when staying within or right after the current chunk
+                    let keep = self.current.as_ref().is_some_and(|(_, (start, data))| {
+                        (*start..=start + data.len() as u64).contains(&position)
+                    });
+                    if !keep {
+                        self.current = None;
+                        self.ahead.clear();
+                    }
+                }
+                self.position = position;
+                Poll::Ready(Ok(position))
+            }
+            None => Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ))),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+const RAW: u8 = 0;
+const LZ4: u8 = 1;
+const ZSTD: u8 = 2;
+
+/// Compression used by [`CompressedStore`](crate::CompressedStore) for new data.
+///
+/// Each blob starts with a one-byte header naming its codec, so data written with any codec can
+/// be read back regardless of the one currently configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Lz4,
+    /// Zstandard at the given level.
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+    Zstd(i32),
+}
+
+impl Codec {
+    fn tag(self) -> u8 {
+        match self {
+            Self::Lz4 => LZ4,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => ZSTD,
+        }
+    }
+
+    /// `data` compressed and prefixed with the header, or `None` if that turns out no shorter
+    /// than `data` itself.
+    pub fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut compressed = vec![self.tag()];
+        match self {
+            Self::Lz4 => compressed.extend(lz4_flex::compress_prepend_size(data)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => compressed.extend(zstd::encode_all(data, level).ok()?),
+        }
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+
+    /// Same as [`Codec::compress`], but keeps incompressible `data` as-is behind a header.
+    pub fn encode(self, data: &[u8]) -> Vec<u8> {
+        self.compress(data)
+            .unwrap_or_else(|| [&[RAW], data].concat())
+    }
+}
+
+/// Inverse of [`Codec::compress`] and [`Codec::encode`] for any codec.
+pub fn decode(data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+    let (&tag, data) = data
+        .split_first()
+        .ok_or_else(|| object_rainbow::error_consistency!("missing codec header"))?;
+    match tag {
+        RAW => Ok(data.to_vec()),
+        LZ4 => {
+            lz4_flex::decompress_size_prepended(data).map_err(object_rainbow::Error::consistency)
+        }
+        #[cfg(feature = "zstd")]
+        ZSTD => zstd::decode_all(data).map_err(object_rainbow::Error::consistency),
+        #[cfg(not(feature = "zstd"))]
+        ZSTD => Err(object_rainbow::error_operation!(
+            "zstd-compressed data needs the `zstd` feature"
+        )),
+        _ => Err(object_rainbow::error_consistency!("unknown codec {tag}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Codec, decode};
+
+    fn roundtrip(codec: Codec) {
+        let text = b"abcabcabc".repeat(100);
+        let compressed = codec.compress(&text).unwrap();
+        assert!(compressed.len() < text.len());
+        assert_eq!(decode(&compressed).unwrap(), text);
+        assert_eq!(codec.compress(b"abc"), None);
+        assert_eq!(codec.encode(b"abc"), b"\0abc");
+        assert_eq!(decode(&codec.encode(b"abc")).unwrap(), b"abc");
+        assert_eq!(decode(&codec.encode(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn lz4() {
+        roundtrip(Codec::Lz4);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        roundtrip(Codec::Zstd(3));
+        assert_eq!(
+            decode(&Codec::Zstd(3).encode(&[1; 1000])).unwrap(),
+            decode(&Codec::Lz4.encode(&[1; 1000])).unwrap(),
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(decode(b"").is_err());
+        assert!(decode(b"\x7fabc").is_err());
+        assert!(decode(b"\x01abc").is_err());
+    }
+}
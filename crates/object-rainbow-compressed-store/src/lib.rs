@@ -0,0 +1,216 @@
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+use object_rainbow::{ToOutput, WithHash};
+use object_rainbow_store::{ExternalStore, ObjectIndex};
+
+pub use self::codec::{Codec, decode};
+
+mod codec;
+mod rainbow_store;
+
+/// Compresses data on its way into `S` and decompresses it on the way out.
+///
+/// Hashes are still over the uncompressed data, so wrapping a store doesn't change any object's
+/// hash or id. An [`ExternalStore`] gets the original [`WithHash`] alongside the compressed bytes.
+/// A [`RainbowStoreMut`] gets each object's hash followed by its compressed data, kept through an
+/// [`ObjectIndex`] under that hash.
+///
+/// [`RainbowStoreMut`]: object_rainbow_store::RainbowStoreMut
+#[derive(Clone, PartialEq)]
+pub struct CompressedStore<S> {
+    index: ObjectIndex<S>,
+    codec: Codec,
+}
+
+impl<S> CompressedStore<S> {
+    pub fn new(store: S) -> Self {
+        Self::with_codec(store, Codec::default())
+    }
+
+    pub fn with_codec(store: S, codec: Codec) -> Self {
+        Self {
+            index: ObjectIndex::new(store),
+            codec,
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+}
+
+impl<S: ExternalStore> ExternalStore for CompressedStore<S> {
+    type Id = S::Id;
+
+    async fn save_data(
+        &self,
+        data: &[u8],
+        refs: &[Self::Id],
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<Self::Id> {
+        self.index
+            .store()
+            .save_data(&self.codec.encode(data), refs, wh)
+            .await
+    }
+
+    async fn contains_data(
+        &self,
+        data: &[u8],
+        refs: &[Self::Id],
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<bool> {
+        self.index
+            .store()
+            .contains_data(&self.codec.encode(data), refs, wh)
+            .await
+    }
+
+    async fn contains(&self, id: &Self::Id) -> object_rainbow::Result<bool> {
+        self.index.store().contains(id).await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, id: &Self::Id) -> object_rainbow::Result<Vec<u8>> {
+        decode(self.index.store().fetch(id).await?.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use futures_util::TryStreamExt;
+    use macro_rules_attribute::apply;
+    use object_rainbow::{Fetch, FullHash, Hash, Singular, ToOutput, WithHash};
+    use object_rainbow_point::{IntoPoint, Point};
+    use object_rainbow_store::{ExternalStore, Gc, RainbowStore, RainbowStoreMut, StoreMut};
+    use object_rainbow_store_fs::FsStore;
+    use object_rainbow_store_opendal::OpendalStore;
+    use opendal::{Operator, services::Memory};
+    use smol_macros::test;
+
+    use crate::CompressedStore;
+
+    #[derive(Clone, Default)]
+    struct MapStore(Arc<Mutex<BTreeMap<Hash, Vec<u8>>>>);
+
+    impl PartialEq for MapStore {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+
+    impl ExternalStore for MapStore {
+        type Id = Hash;
+
+        async fn save_data(
+            &self,
+            data: &[u8],
+            _: &[Self::Id],
+            wh: WithHash<'_, impl Send + Sync + ToOutput>,
+        ) -> object_rainbow::Result<Self::Id> {
+            let hash = wh.data_hash();
+            self.0.lock().unwrap().insert(hash, data.to_vec());
+            Ok(hash)
+        }
+
+        async fn contains_data(
+            &self,
+            _: &[u8],
+            _: &[Self::Id],
+            wh: WithHash<'_, impl Send + Sync + ToOutput>,
+        ) -> object_rainbow::Result<bool> {
+            self.contains(&wh.data_hash()).await
+        }
+
+        async fn contains(&self, id: &Self::Id) -> object_rainbow::Result<bool> {
+            Ok(self.0.lock().unwrap().contains_key(id))
+        }
+
+        #[expect(refining_impl_trait)]
+        async fn fetch(&self, id: &Self::Id) -> object_rainbow::Result<Vec<u8>> {
+            self.0
+                .lock()
+                .unwrap()
+                .get(id)
+                .cloned()
+                .ok_or(object_rainbow::Error::HashNotFound)
+        }
+    }
+
+    #[apply(test!)]
+    async fn external_store() -> object_rainbow::Result<()> {
+        let inner = MapStore::default();
+        let store = CompressedStore::new(inner.clone());
+        let object = ([7u8; 1000], *b"abc");
+        let id = store.store_object(object).await?;
+        assert_eq!(id, object.full_hash());
+        assert!(inner.fetch(&id).await?.len() < 100);
+        assert_eq!(store.load::<([u8; 1000], [u8; 3])>(&id).await?, object);
+        let id = store.store_object(*b"abc").await?;
+        assert_eq!(id, b"abc".full_hash());
+        assert_eq!(inner.fetch(&id).await?[0], 0);
+        assert_eq!(store.load::<[u8; 3]>(&id).await?, *b"abc");
+        Ok(())
+    }
+
+    async fn rainbow_store_over(inner: impl RainbowStoreMut) -> object_rainbow::Result<()> {
+        let store = CompressedStore::new(inner.clone());
+        let small = (*b"abc").point();
+        let point = (small.clone(), [7u8; 1000]).point();
+        StoreMut::new(store.clone())
+            .init("test", point.clone())
+            .await?;
+        assert!(store.contains(point.hash()).await?);
+        assert!(!inner.ref_exists("test").await?);
+        let loaded = StoreMut::new(store.clone())
+            .load::<(Point<[u8; 3]>, [u8; 1000]), _>("test")
+            .await?;
+        assert_eq!(loaded.hash(), point.hash());
+        let (small, large) = loaded.fetch().await?;
+        assert_eq!(small.fetch().await?, *b"abc");
+        assert_eq!(large, [7; 1000]);
+        let orphan = (*b"xyz").point();
+        store.save_point(&orphan).await?;
+        let mut listed = store
+            .list_objects()
+            .map_ok(|(hash, _)| hash)
+            .try_collect::<Vec<_>>()
+            .await?;
+        listed.sort();
+        let mut expected = vec![small.hash(), point.hash(), orphan.hash()];
+        expected.sort();
+        assert_eq!(listed, expected);
+        let report = Gc::new(store.clone())
+            .with_grace_period(Duration::ZERO)
+            .with_sweep_untimed(true)
+            .refs::<(Point<[u8; 3]>, [u8; 1000])>("")
+            .run()
+            .await?;
+        assert_eq!(report.swept, [orphan.hash()]);
+        assert!(!store.contains(orphan.hash()).await?);
+        assert!(store.contains(small.hash()).await?);
+        store.rename_ref("test", "renamed").await?;
+        let refs = store.list_refs("re").try_collect::<Vec<_>>().await?;
+        assert_eq!(refs, [("renamed".to_owned(), point.hash())]);
+        store.delete_ref("renamed", None).await?;
+        assert!(!store.ref_exists("renamed").await?);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn rainbow_store() -> anyhow::Result<()> {
+        rainbow_store_over(OpendalStore::from_operator(
+            Operator::new(Memory::default())?.finish(),
+        ))
+        .await?;
+        let dir = tempfile::tempdir()?;
+        rainbow_store_over(FsStore::new(dir.path())).await?;
+        Ok(())
+    }
+}
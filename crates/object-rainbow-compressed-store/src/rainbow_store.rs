@@ -0,0 +1,130 @@
+use std::{pin::pin, time::SystemTime};
+
+use futures_util::{Stream, TryStreamExt};
+use genawaiter_try_stream::try_stream;
+use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash};
+use object_rainbow_store::{RainbowStore, RainbowStoreMut, RefUpdate, ReflogEntry};
+
+use crate::{CompressedStore, decode};
+
+impl<S: RainbowStoreMut> CompressedStore<S> {
+    fn blob(&self, wh: WithHash<'_, impl ToOutput>) -> (Hash, Vec<u8>) {
+        let hash = wh.data_hash();
+        (hash, (hash, self.codec.encode(&wh.data.vec())).vec())
+    }
+
+    fn open(blob: &[u8]) -> object_rainbow::Result<(Hash, Vec<u8>)> {
+        <(Hash, Vec<u8>)>::parse_slice_refless(blob)
+    }
+}
+
+impl<S: RainbowStoreMut> RainbowStore for CompressedStore<S> {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        let (hash, blob) = self.blob(wh);
+        self.index.save(hash, &blob).await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        self.index.contains(hash).await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Vec<u8>> {
+        let (actual, encoded) = Self::open(&self.index.fetch(hash).await?)?;
+        if actual != hash {
+            return Err(object_rainbow::error_consistency!("hash mismatch"));
+        }
+        decode(&encoded)
+    }
+
+    /// Reads the hash in front of every object that isn't found through an index ref, and skips
+    /// those not stored under it, like ones left behind by a failed save.
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        try_stream(async move |co| {
+            let store = self.index.store();
+            let mut objects = pin!(
+                self.index
+                    .list()
+                    .map_ok(|(hash, stored, written)| async move {
+                        let listed = hash != stored
+                            || Self::open(store.fetch(stored).await?.as_ref())?.0 == hash;
+                        Ok((hash, written, listed))
+                    })
+                    .try_buffer_unordered(self.concurrency())
+            );
+            while let Some((hash, written, listed)) = objects.try_next().await? {
+                if listed {
+                    co.yield_((hash, written)).await;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        self.index.delete(hashes).await
+    }
+
+    async fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> object_rainbow::Result<()> {
+        let blobs = objects.into_iter().map(|wh| self.blob(wh)).collect();
+        self.index.save_many(blobs).await
+    }
+}
+
+impl<S: RainbowStoreMut> RainbowStoreMut for CompressedStore<S> {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.index.update_ref(key, old, hash).await
+    }
+
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        self.index.update_refs(updates).await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        self.index.fetch_ref(key).await
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        self.index.ref_exists(key).await
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        self.index.list_refs(prefix)
+    }
+
+    async fn reflog(&self, key: &str) -> object_rainbow::Result<Vec<ReflogEntry>> {
+        self.index.reflog(key).await
+    }
+
+    fn list_reflogs(&self) -> impl Send + Stream<Item = object_rainbow::Result<String>> {
+        self.index.list_reflogs()
+    }
+
+    fn watch(&self, key: &str) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        self.index.watch(key)
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        self.index.delete_ref(key, old).await
+    }
+
+    async fn rename_ref(&self, from: &str, to: &str) -> object_rainbow::Result<()> {
+        self.index.rename_ref(from, to).await
+    }
+}
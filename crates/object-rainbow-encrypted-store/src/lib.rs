@@ -1,16 +1,81 @@
-use object_rainbow::{DiffHashes, ToOutput, WithHash};
-use object_rainbow_encrypted::Key;
+use futures_util::future::try_join_all;
+use object_rainbow::{DiffHashes, Hash, ToOutput, WithHash};
+use object_rainbow_encrypted::{Key, Padding};
 use object_rainbow_store::ExternalStore;
 
+pub use self::rainbow_store::EncryptedRainbowStore;
+
+mod rainbow_store;
+
+const DECOY_PREFIX: &[u8] = b"this encrypted constant pads the references of another object";
+
+/// Wrap `K` in [`Padded`] to also hide data lengths.
+///
+/// [`Padded`]: object_rainbow_encrypted::Padded
 #[derive(Clone, PartialEq)]
 pub struct EncryptedStore<S, K> {
     store: S,
     key: K,
+    refs_padding: Padding,
 }
 
 impl<S, K> EncryptedStore<S, K> {
     pub fn new(store: S, key: K) -> Self {
-        Self { store, key }
+        Self {
+            store,
+            key,
+            refs_padding: Padding::None,
+        }
+    }
+
+    /// Pad the references passed to the underlying store with distinct decoy leaves. The actual
+    /// topology is kept in the encrypted data, so decoys never make it back out on fetch. Leaves
+    /// stay leaves.
+    ///
+    /// Decoys are encrypted with `K` too; with convergent nonces they are shared between objects.
+    pub fn with_refs_padding(self, refs_padding: Padding) -> Self {
+        Self {
+            refs_padding,
+            ..self
+        }
+    }
+}
+
+impl<S: ExternalStore, K: Key> EncryptedStore<S, K> {
+    fn diff(&self, refs: &[S::Id], data: impl ToOutput) -> object_rainbow::Result<Hash> {
+        Ok(DiffHashes {
+            tags: Default::default(),
+            topology: refs.data_hash(),
+            mangle: (self.key.mangle_prefix()?.data_hash(), data.data_hash()).data_hash(),
+        }
+        .data_hash())
+    }
+
+    async fn decoy(&self, index: u64) -> object_rainbow::Result<S::Id> {
+        let mut data = DECOY_PREFIX.to_vec();
+        data.extend_from_slice(&index.to_le_bytes());
+        let encrypted = &self.key.encrypt(&data)?;
+        let diff = self.diff(&[], &data)?;
+        self.store
+            .save_data(
+                encrypted,
+                &[],
+                WithHash {
+                    diff,
+                    data: encrypted,
+                },
+            )
+            .await
+    }
+
+    async fn refs(&self, refs: &[S::Id]) -> object_rainbow::Result<Vec<S::Id>> {
+        let len = if refs.is_empty() {
+            0
+        } else {
+            self.refs_padding.padded_len(refs.len())
+        };
+        let decoys = try_join_all((refs.len()..len).map(|index| self.decoy(index as u64))).await?;
+        Ok(refs.iter().cloned().chain(decoys).collect())
     }
 }
 
@@ -23,42 +88,37 @@ impl<S: ExternalStore, K: Key> ExternalStore for EncryptedStore<S, K> {
         refs: &[Self::Id],
         wh: WithHash<'_, impl ToOutput>,
     ) -> object_rainbow::Result<Self::Id> {
-        let encrypted = &self.key.encrypt(data);
-        let diff = DiffHashes {
-            tags: Default::default(),
-            topology: refs.data_hash(),
-            mangle: (self.key.mangle_prefix().data_hash(), wh.data.data_hash()).data_hash(),
-        };
+        let encrypted = &self.key.encrypt(data)?;
+        let refs = &self.refs(refs).await?;
+        let diff = self.diff(refs, wh.data)?;
         self.store
             .save_data(
                 encrypted,
                 refs,
                 WithHash {
-                    diff: diff.data_hash(),
+                    diff,
                     data: encrypted,
                 },
             )
             .await
     }
 
+    /// With reference padding, this saves the decoys, since their ids are part of the object's.
     async fn contains_data(
         &self,
         data: &[u8],
         refs: &[Self::Id],
         wh: WithHash<'_, impl Send + Sync + ToOutput>,
     ) -> object_rainbow::Result<bool> {
-        let encrypted = &self.key.encrypt(data);
-        let diff = DiffHashes {
-            tags: Default::default(),
-            topology: refs.data_hash(),
-            mangle: (self.key.mangle_prefix().data_hash(), wh.data.data_hash()).data_hash(),
-        };
+        let encrypted = &self.key.encrypt(data)?;
+        let refs = &self.refs(refs).await?;
+        let diff = self.diff(refs, wh.data)?;
         self.store
             .contains_data(
                 encrypted,
                 refs,
                 WithHash {
-                    diff: diff.data_hash(),
+                    diff,
                     data: encrypted,
                 },
             )
@@ -79,13 +139,16 @@ impl<S: ExternalStore, K: Key> ExternalStore for EncryptedStore<S, K> {
 
 #[cfg(test)]
 mod test {
-    use std::convert::Infallible;
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
 
     use macro_rules_attribute::apply;
     use object_rainbow::{FullHash, Hash, ToOutput, WithHash};
-    use object_rainbow_encrypted::{Key, encrypt};
-    use object_rainbow_point::IntoPoint;
-    use object_rainbow_store::ExternalStore;
+    use object_rainbow_encrypted::{Key, Padded, Padding, encrypt};
+    use object_rainbow_point::{IntoPoint, Point};
+    use object_rainbow_store::{ExternalStore, RainbowStoreMut};
     use smol_macros::test;
 
     use crate::EncryptedStore;
@@ -130,8 +193,8 @@ mod test {
     impl Key for InverseKey {
         type Error = Infallible;
 
-        fn encrypt(&self, data: &[u8]) -> Vec<u8> {
-            data.iter().copied().map(|x| !x).collect()
+        fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+            Ok(data.iter().copied().map(|x| !x).collect())
         }
 
         fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
@@ -149,4 +212,224 @@ mod test {
         assert_eq!(f, h);
         Ok(())
     }
+
+    type Saved = (usize, Vec<Hash>);
+
+    #[derive(Clone, Default)]
+    struct RecordingStore(Arc<Mutex<Vec<Saved>>>);
+
+    impl PartialEq for RecordingStore {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+
+    impl ExternalStore for RecordingStore {
+        type Id = Hash;
+
+        async fn save_data(
+            &self,
+            data: &[u8],
+            refs: &[Self::Id],
+            wh: WithHash<'_, impl Send + Sync + ToOutput>,
+        ) -> object_rainbow::Result<Self::Id> {
+            self.0.lock().unwrap().push((data.len(), refs.to_vec()));
+            Ok(wh.data_hash())
+        }
+
+        async fn contains_data(
+            &self,
+            _: &[u8],
+            _: &[Self::Id],
+            _: WithHash<'_, impl Send + Sync + ToOutput>,
+        ) -> object_rainbow::Result<bool> {
+            unimplemented!()
+        }
+
+        async fn contains(&self, _: &Self::Id) -> object_rainbow::Result<bool> {
+            unimplemented!()
+        }
+
+        #[expect(refining_impl_trait)]
+        async fn fetch(&self, _: &Self::Id) -> object_rainbow::Result<Vec<u8>> {
+            unimplemented!()
+        }
+    }
+
+    #[apply(test!)]
+    async fn padding() -> object_rainbow::Result<()> {
+        let o = (
+            *b"abc",
+            (*b"b").point(),
+            (*b"c").point(),
+            (*b"defgh").point(),
+        );
+        let r = RecordingStore::default();
+        let s = EncryptedStore::new(r.clone(), Padded::new(InverseKey, Padding::Block(64)))
+            .with_refs_padding(Padding::PowerOfTwo);
+        s.store_object(o).await?;
+        let saved = r.0.lock().unwrap().clone();
+        assert_eq!(saved.len(), 5);
+        assert!(saved.iter().all(|(data, _)| data % 64 == 0));
+        assert!(
+            saved
+                .iter()
+                .all(|(_, refs)| refs.is_empty() || refs.len() == 4)
+        );
+        let (_, refs) = saved.last().unwrap();
+        assert_eq!(refs.len(), 4);
+        assert!((1..4).all(|i| !refs[..i].contains(&refs[i])));
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn rainbow_store() -> anyhow::Result<()> {
+        use futures_util::TryStreamExt;
+        use object_rainbow::{Fetch, Singular};
+        use object_rainbow_history_store::HistoryStore;
+        use object_rainbow_store::{RainbowStore, RainbowStoreMut, StoreMut};
+        use object_rainbow_store_opendal::OpendalStore;
+        use object_rainbow_trie::TrieMap;
+        use opendal::{Operator, services::Memory};
+
+        use crate::EncryptedRainbowStore;
+
+        let inner = OpendalStore::from_operator(Operator::new(Memory::default())?.finish());
+        let store = EncryptedRainbowStore::new(inner.clone(), InverseKey).await?;
+        let point = ((*b"abc").point(), *b"def").point();
+        let stored = StoreMut::new(store.clone())
+            .init("test", point.clone())
+            .await?;
+        assert!(store.contains(point.hash()).await?);
+        assert!(!inner.contains(point.hash()).await?);
+        assert!(!inner.ref_exists("test").await?);
+        let loaded = StoreMut::new(store.clone())
+            .load::<(Point<[u8; 3]>, [u8; 3]), _>("test")
+            .await?;
+        assert_eq!(loaded.hash(), stored.hash());
+        assert_eq!(loaded.fetch().await?.0.fetch().await?, *b"abc");
+        store.rename_ref("test", "renamed").await?;
+        let refs = store.list_refs("").try_collect::<Vec<_>>().await?;
+        assert_eq!(refs, [("renamed".to_owned(), point.hash())]);
+        store
+            .delete_ref("renamed", Some(point.hash().into()))
+            .await?;
+        assert!(!store.ref_exists("renamed").await?);
+        let history =
+            HistoryStore::<TrieMap<Vec<u8>, u8>, (Option<u8>, Vec<u8>), _>::new("main", store);
+        history.commit((Some(123), b"abc".into())).await?;
+        assert_eq!(history.load().await?.get(&b"abc".into()).await?, Some(123));
+        history.commit((None, b"abc".into())).await?;
+        assert!(history.load().await?.get(&b"abc".into()).await?.is_none());
+        Ok(())
+    }
+
+    /// Encrypts the same data differently each time, like random nonces do.
+    #[derive(Clone, Default)]
+    struct NoncedKey(Arc<Mutex<u8>>);
+
+    impl PartialEq for NoncedKey {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+
+    impl Eq for NoncedKey {}
+
+    impl Key for NoncedKey {
+        type Error = Infallible;
+
+        fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+            let mut nonce = self.0.lock().unwrap();
+            *nonce = nonce.wrapping_add(1);
+            Ok([&[*nonce], data].concat())
+        }
+
+        fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+            Ok(data[1..].to_vec())
+        }
+    }
+
+    #[apply(test!)]
+    async fn reopen() -> anyhow::Result<()> {
+        use object_rainbow::{Fetch, Singular};
+        use object_rainbow_store::{RainbowStore, StoreMut};
+        use object_rainbow_store_opendal::OpendalStore;
+        use opendal::{Operator, services::Memory};
+
+        use crate::EncryptedRainbowStore;
+
+        type Tree = (Point<[u8; 3]>, [u8; 3]);
+
+        let inner = OpendalStore::from_operator(Operator::new(Memory::default())?.finish());
+        let key = NoncedKey::default();
+        let store = EncryptedRainbowStore::new(inner.clone(), key.clone()).await?;
+        let point = ((*b"abc").point(), *b"def").point();
+        StoreMut::new(store).init("test", point.clone()).await?;
+        let store = EncryptedRainbowStore::new(inner.clone(), key.clone()).await?;
+        assert!(store.contains(point.hash()).await?);
+        let loaded = StoreMut::new(store.clone()).load::<Tree, _>("test").await?;
+        assert_eq!(loaded.hash(), point.hash());
+        assert_eq!(loaded.fetch().await?.0.fetch().await?, *b"abc");
+        assert!(EncryptedRainbowStore::new(inner, InverseKey).await.is_err());
+        Ok(())
+    }
+
+    async fn gc_over(inner: impl RainbowStoreMut) -> object_rainbow::Result<()> {
+        use std::time::Duration;
+
+        use futures_util::TryStreamExt;
+        use object_rainbow::{Fetch, Singular};
+        use object_rainbow_store::{Gc, RainbowStore, StoreMut};
+
+        use crate::EncryptedRainbowStore;
+
+        type Tree = (Point<[u8; 3]>, [u8; 3]);
+
+        let store = EncryptedRainbowStore::new(inner.clone(), InverseKey).await?;
+        let point = ((*b"abc").point(), *b"def").point();
+        StoreMut::new(store.clone())
+            .init("test", point.clone())
+            .await?;
+        let orphan = (*b"xyz").point();
+        store.save_point(&orphan).await?;
+        assert!(!inner.contains(orphan.hash()).await?);
+        let mut listed = store
+            .list_objects()
+            .map_ok(|(hash, _)| hash)
+            .try_collect::<Vec<_>>()
+            .await?;
+        listed.sort();
+        let mut expected = vec![point.hash(), point.fetch().await?.0.hash(), orphan.hash()];
+        expected.sort();
+        assert_eq!(listed, expected);
+        let gc = || {
+            Gc::new(store.clone())
+                .with_grace_period(Duration::ZERO)
+                .refs::<Tree>("")
+        };
+        let dry = gc().with_dry_run(true).run().await?;
+        assert_eq!(dry.swept.len() + dry.untimed, 1);
+        let report = gc().with_sweep_untimed(true).run().await?;
+        assert_eq!(report.swept, [orphan.hash()]);
+        assert!(!store.contains(orphan.hash()).await?);
+        let loaded = StoreMut::new(store.clone()).load::<Tree, _>("test").await?;
+        assert_eq!(loaded.fetch().await?.0.fetch().await?, *b"abc");
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn gc() -> anyhow::Result<()> {
+        use object_rainbow_store_fs::FsStore;
+        use object_rainbow_store_opendal::OpendalStore;
+        use opendal::{Operator, services::Memory};
+
+        gc_over(OpendalStore::from_operator(
+            Operator::new(Memory::default())?.finish(),
+        ))
+        .await?;
+        let dir = tempfile::tempdir()?;
+        gc_over(FsStore::new(dir.path())).await?;
+        Ok(())
+    }
 }
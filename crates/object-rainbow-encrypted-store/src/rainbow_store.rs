@@ -0,0 +1,242 @@
+use std::{pin::pin, time::SystemTime};
+
+use futures_util::{Stream, TryStreamExt};
+use genawaiter_try_stream::try_stream;
+use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash};
+use object_rainbow_encrypted::Key;
+use object_rainbow_store::{ObjectIndex, RainbowStore, RainbowStoreMut, RefUpdate, ReflogEntry};
+
+const SALT: &[u8] = b"this encrypted store keeps its salt under the hash of this constant";
+
+/// Where the salt is kept in the [`ObjectIndex`]. Not a keyed hash of anything, so it's never an
+/// object's id.
+fn salt_id() -> Hash {
+    SALT.data_hash()
+}
+
+/// [`RainbowStore`] that keeps everything in `S` encrypted with `K`.
+///
+/// Objects are kept through an [`ObjectIndex`] under keyed hashes of their plaintext hashes, so
+/// neither those nor the object graph are visible to `S`. Refs point to the same keyed hashes.
+/// The salt they're keyed with is kept in `S` too, encrypted.
+#[derive(Clone, PartialEq)]
+pub struct EncryptedRainbowStore<S, K> {
+    index: ObjectIndex<S>,
+    key: K,
+    salt: Hash,
+}
+
+impl<S: RainbowStoreMut, K: Key> EncryptedRainbowStore<S, K> {
+    /// Read the salt from `S`, which fails if it was saved with another key. A store that has none
+    /// yet gets one derived from [`Key::mangle_prefix`]. Opening a new store from two places at
+    /// once may leave them with different salts if `K` doesn't encrypt deterministically.
+    pub async fn new(store: S, key: K) -> object_rainbow::Result<Self> {
+        let index = ObjectIndex::new(store);
+        let salt = match index.fetch(salt_id()).await {
+            Ok(blob) => {
+                let salt = key
+                    .decrypt(&blob)
+                    .map_err(object_rainbow::Error::consistency)?;
+                Hash::parse_slice_refless(&salt)?
+            }
+            Err(object_rainbow::Error::HashNotFound) => {
+                let salt = key.mangle_prefix()?.data_hash();
+                index.save(salt_id(), &key.encrypt(&salt.vec())?).await?;
+                salt
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self { index, key, salt })
+    }
+
+    fn id(&self, hash: Hash) -> Hash {
+        (self.salt, hash).data_hash()
+    }
+
+    fn old_id(&self, old: Option<OptionalHash>) -> Option<OptionalHash> {
+        old.map(|old| match old.get() {
+            Some(old) => self.id(old).into(),
+            None => OptionalHash::NONE,
+        })
+    }
+
+    /// The id of `hash`, which must be stored for refs to point to it.
+    async fn locate(&self, hash: Hash) -> object_rainbow::Result<Hash> {
+        let id = self.id(hash);
+        if self.index.contains(id).await? {
+            Ok(id)
+        } else {
+            Err(object_rainbow::Error::HashNotFound)
+        }
+    }
+
+    fn seal(&self, wh: WithHash<'_, impl ToOutput>) -> object_rainbow::Result<(Hash, Vec<u8>)> {
+        let hash = wh.data_hash();
+        Ok((self.id(hash), self.key.encrypt(&(hash, wh.data).vec())?))
+    }
+
+    fn open(&self, blob: &[u8]) -> object_rainbow::Result<(Hash, Vec<u8>)> {
+        let data = self
+            .key
+            .decrypt(blob)
+            .map_err(object_rainbow::Error::consistency)?;
+        <(Hash, Vec<u8>)>::parse_slice_refless(&data)
+    }
+
+    /// Plaintext hash of what `id` points to.
+    async fn resolve(&self, id: Hash) -> object_rainbow::Result<Hash> {
+        Ok(self.open(&self.index.fetch(id).await?)?.0)
+    }
+}
+
+impl<S: RainbowStoreMut, K: Key> RainbowStore for EncryptedRainbowStore<S, K> {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        let (id, blob) = self.seal(wh)?;
+        self.index.save(id, &blob).await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        self.index.contains(self.id(hash)).await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Vec<u8>> {
+        let (actual, data) = self.open(&self.index.fetch(self.id(hash)).await?)?;
+        if actual != hash {
+            return Err(object_rainbow::error_consistency!("hash mismatch"));
+        }
+        Ok(data)
+    }
+
+    /// Decrypts every object to find its hash. Objects of `S` that aren't stored under the keyed
+    /// hash of what they decrypt to, like those left behind by a failed save, are skipped.
+    /// So is the salt.
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        try_stream(async move |co| {
+            let store = self.index.store();
+            let mut objects = pin!(
+                self.index
+                    .list()
+                    .try_filter(|(id, ..)| std::future::ready(*id != salt_id()))
+                    .map_ok(|(id, stored, written)| async move {
+                        let (hash, _) = self.open(store.fetch(stored).await?.as_ref())?;
+                        Ok((id, hash, written))
+                    })
+                    .try_buffer_unordered(self.concurrency())
+            );
+            while let Some((id, hash, written)) = objects.try_next().await? {
+                if self.id(hash) == id {
+                    co.yield_((hash, written)).await;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        let ids = hashes.iter().map(|hash| self.id(*hash)).collect::<Vec<_>>();
+        self.index.delete(&ids).await
+    }
+
+    async fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> object_rainbow::Result<()> {
+        let blobs = objects
+            .into_iter()
+            .map(|wh| self.seal(wh))
+            .collect::<object_rainbow::Result<_>>()?;
+        self.index.save_many(blobs).await
+    }
+}
+
+impl<S: RainbowStoreMut, K: Key> RainbowStoreMut for EncryptedRainbowStore<S, K> {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.index
+            .update_ref(key, self.old_id(old), self.locate(hash).await?)
+            .await
+    }
+
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        let mut located = Vec::with_capacity(updates.len());
+        for RefUpdate { key, old, hash } in updates {
+            located.push(RefUpdate {
+                key,
+                old: self.old_id(old),
+                hash: self.locate(hash).await?,
+            });
+        }
+        self.index.update_refs(located).await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        match self.index.fetch_ref(key).await?.get() {
+            Some(id) => Ok(self.resolve(id).await?.into()),
+            None => Ok(OptionalHash::NONE),
+        }
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        self.index.ref_exists(key).await
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        try_stream(async move |co| {
+            let mut refs = pin!(self.index.list_refs(prefix));
+            while let Some((key, id)) = refs.try_next().await? {
+                co.yield_((key, self.resolve(id).await?)).await;
+            }
+            Ok(())
+        })
+    }
+
+    async fn reflog(&self, key: &str) -> object_rainbow::Result<Vec<ReflogEntry>> {
+        let mut entries = self.index.reflog(key).await?;
+        for entry in &mut entries {
+            for hash in [&mut entry.old, &mut entry.new] {
+                if let Some(id) = hash.get() {
+                    *hash = self.resolve(id).await?.into();
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn list_reflogs(&self) -> impl Send + Stream<Item = object_rainbow::Result<String>> {
+        self.index.list_reflogs()
+    }
+
+    fn watch(&self, key: &str) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        try_stream(async move |co| {
+            let mut ids = pin!(self.index.watch(key));
+            while let Some(id) = ids.try_next().await? {
+                match id.get() {
+                    Some(id) => co.yield_(self.resolve(id).await?.into()).await,
+                    None => co.yield_(OptionalHash::NONE).await,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        self.index.delete_ref(key, self.old_id(old)).await
+    }
+
+    async fn rename_ref(&self, from: &str, to: &str) -> object_rainbow::Result<()> {
+        self.index.rename_ref(from, to).await
+    }
+}
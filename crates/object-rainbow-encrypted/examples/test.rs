@@ -2,8 +2,8 @@ use chacha20poly1305::{
     ChaCha20Poly1305,
     aead::{Aead, generic_array::GenericArray},
 };
-use object_rainbow::{Fetch, Object};
-use object_rainbow_encrypted::{Key, encrypt_point};
+use object_rainbow::{Fetch, FullHash, Object, Singular};
+use object_rainbow_encrypted::{Key, encrypt_point, reencrypt};
 use object_rainbow_fetchall::fetchall;
 use object_rainbow_point::{IntoPoint, Point};
 
@@ -13,7 +13,7 @@ struct Test([u8; 32]);
 impl Key for Test {
     type Error = chacha20poly1305::Error;
 
-    fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+    fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
         println!("encrypt");
         let cipher = {
             use chacha20poly1305::KeyInit;
@@ -28,8 +28,8 @@ impl Key for Test {
         let nonce = &nonce.as_slice()[..12];
         let encrypted = cipher
             .encrypt(GenericArray::from_slice(nonce), data)
-            .expect("we do not handle decryption errors");
-        [nonce, encrypted.as_slice()].concat()
+            .map_err(object_rainbow::Error::operation)?;
+        Ok([nonce, encrypted.as_slice()].concat())
     }
 
     fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
@@ -78,6 +78,21 @@ fn main() -> anyhow::Result<()> {
             point.fetch().await?.0.fetch().await?.fetch().await?.1,
             *b"feistel",
         );
+        let old_key = key;
+        let new_key = Test(std::array::from_fn(|i| !i as _));
+        let point = iterate(encrypt_point(old_key, point).await?, old_key).await?;
+        assert!(reencrypt(point.clone(), &new_key, new_key).await.is_err());
+        let reencrypted = reencrypt(point.clone(), &old_key, new_key).await?;
+        let reencrypted = iterate(reencrypted, new_key).await?;
+        assert_ne!(point.hash(), reencrypted.hash());
+        assert_eq!(
+            point.fetch().await?.into_inner().full_hash(),
+            reencrypted.fetch().await?.into_inner().full_hash(),
+        );
+        assert_eq!(
+            reencrypted.fetch().await?.0.fetch().await?.fetch().await?.1,
+            *b"feistel",
+        );
         println!("all right");
         Ok(())
     })
@@ -0,0 +1,257 @@
+use std::marker::PhantomData;
+
+use aead::{
+    Aead, KeyInit, Nonce, OsRng,
+    consts::U32,
+    generic_array::{GenericArray, typenum::Unsigned},
+    rand_core::RngCore,
+};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{DataKey, Key, MANGLE_PREFIX};
+
+/// How [`AeadKey`] picks a nonce for each encryption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nonces {
+    /// Derived from a keyed hash of the plaintext. Equal objects encrypt to equal bytes and so
+    /// still deduplicate, at the cost of revealing to the store which objects are equal.
+    Convergent,
+    /// Freshly generated each time. Encrypting the same object twice gives different bytes, and
+    /// hence a different hash.
+    Random,
+}
+
+/// [`Key`] backed by an AEAD cipher with a 256-bit key. The nonce is stored in front of the
+/// ciphertext.
+pub struct AeadKey<A> {
+    key: Zeroizing<[u8; 32]>,
+    nonces: Nonces,
+    _cipher: PhantomData<fn() -> A>,
+}
+
+#[cfg(feature = "xchacha20poly1305")]
+#[cfg_attr(docsrs, doc(cfg(feature = "xchacha20poly1305")))]
+pub type XChaCha20Poly1305Key = AeadKey<chacha20poly1305::XChaCha20Poly1305>;
+
+#[cfg(feature = "aes-gcm-siv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aes-gcm-siv")))]
+pub type Aes256GcmSivKey = AeadKey<aes_gcm_siv::Aes256GcmSiv>;
+
+impl<A> AeadKey<A> {
+    pub fn new(key: [u8; 32], nonces: Nonces) -> Self {
+        Self {
+            key: Zeroizing::new(key),
+            nonces,
+            _cipher: PhantomData,
+        }
+    }
+
+    /// Fresh random key, e.g. for an [`Envelope`](crate::Envelope).
+    pub fn generate(nonces: Nonces) -> Self {
+        let mut key = Zeroizing::new([0; 32]);
+        OsRng.fill_bytes(key.as_mut());
+        Self::new(*key, nonces)
+    }
+
+    pub fn nonces(&self) -> Nonces {
+        self.nonces
+    }
+
+    /// Derive the key from a passphrase with the default [`argon2::Argon2`] parameters.
+    ///
+    /// `argon2` only pulls in [`AeadKey`] itself; enable `aes-gcm-siv` or `xchacha20poly1305`
+    /// for a ready-made cipher, or bring any 256-bit [`Aead`].
+    #[cfg(feature = "argon2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "argon2")))]
+    pub fn from_passphrase(
+        passphrase: &[u8],
+        salt: &[u8],
+        nonces: Nonces,
+    ) -> Result<Self, argon2::Error> {
+        Self::from_passphrase_with(&Default::default(), passphrase, salt, nonces)
+    }
+
+    #[cfg(feature = "argon2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "argon2")))]
+    pub fn from_passphrase_with(
+        argon2: &argon2::Argon2,
+        passphrase: &[u8],
+        salt: &[u8],
+        nonces: Nonces,
+    ) -> Result<Self, argon2::Error> {
+        let mut key = Zeroizing::new([0; 32]);
+        argon2.hash_password_into(passphrase, salt, key.as_mut())?;
+        Ok(Self::new(*key, nonces))
+    }
+}
+
+impl<A> Clone for AeadKey<A> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key.clone(),
+            nonces: self.nonces,
+            _cipher: PhantomData,
+        }
+    }
+}
+
+impl<A> PartialEq for AeadKey<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.nonces == other.nonces
+    }
+}
+
+impl<A> Eq for AeadKey<A> {}
+
+impl<A> std::fmt::Debug for AeadKey<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AeadKey")
+            .field("nonces", &self.nonces)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A: KeyInit<KeySize = U32> + Aead> AeadKey<A> {
+    fn cipher(&self) -> A {
+        A::new(GenericArray::from_slice(self.key.as_ref()))
+    }
+
+    fn convergent_nonce(&self, data: &[u8]) -> Nonce<A> {
+        let hash = Sha256::new()
+            .chain_update(b"object-rainbow-encrypted convergent nonce")
+            .chain_update(self.key.as_ref())
+            .chain_update(data)
+            .finalize();
+        GenericArray::clone_from_slice(&hash[..A::NonceSize::USIZE])
+    }
+
+    fn seal(&self, nonce: Nonce<A>, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+        let encrypted = self
+            .cipher()
+            .encrypt(&nonce, data)
+            .map_err(object_rainbow::Error::operation)?;
+        Ok([nonce.as_slice(), &encrypted].concat())
+    }
+}
+
+impl<A: 'static + KeyInit<KeySize = U32> + Aead> Key for AeadKey<A> {
+    type Error = aead::Error;
+
+    fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+        let nonce = match self.nonces {
+            Nonces::Convergent => self.convergent_nonce(data),
+            Nonces::Random => A::generate_nonce(&mut OsRng),
+        };
+        self.seal(nonce, data)
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        if data.len() < A::NonceSize::USIZE {
+            return Err(aead::Error);
+        }
+        let (nonce, encrypted) = data.split_at(A::NonceSize::USIZE);
+        self.cipher()
+            .decrypt(GenericArray::from_slice(nonce), encrypted)
+    }
+
+    /// Always convergent, since it takes part in hashing.
+    fn mangle_prefix(&self) -> object_rainbow::Result<Vec<u8>> {
+        self.seal(self.convergent_nonce(MANGLE_PREFIX), MANGLE_PREFIX)
+    }
+}
+
+impl<A: 'static + KeyInit<KeySize = U32> + Aead> DataKey for AeadKey<A> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let nonces = match self.nonces {
+            Nonces::Convergent => 0,
+            Nonces::Random => 1,
+        };
+        [&[nonces], self.key.as_slice()].concat()
+    }
+
+    fn from_bytes(data: &[u8]) -> object_rainbow::Result<Self> {
+        let (&nonces, key) = data
+            .split_first()
+            .ok_or(object_rainbow::Error::UnsupportedLength)?;
+        let nonces = match nonces {
+            0 => Nonces::Convergent,
+            1 => Nonces::Random,
+            _ => return Err(object_rainbow::error_parse!("unknown nonce mode")),
+        };
+        let key = key
+            .try_into()
+            .map_err(|_| object_rainbow::Error::UnsupportedLength)?;
+        Ok(Self::new(key, nonces))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip<A: 'static + KeyInit<KeySize = U32> + Aead>() -> object_rainbow::Result<()> {
+        let data = b"some data";
+        let convergent = AeadKey::<A>::new([1; 32], Nonces::Convergent);
+        let random = AeadKey::<A>::new([1; 32], Nonces::Random);
+        let other = AeadKey::<A>::new([2; 32], Nonces::Convergent);
+        assert_eq!(convergent.encrypt(data)?, convergent.encrypt(data)?);
+        assert_ne!(random.encrypt(data)?, random.encrypt(data)?);
+        assert_ne!(convergent.encrypt(data)?, other.encrypt(data)?);
+        assert_eq!(random.mangle_prefix()?, random.mangle_prefix()?);
+        assert_eq!(random.mangle_prefix()?, convergent.mangle_prefix()?);
+        assert_eq!(convergent.decrypt(&random.encrypt(data)?).unwrap(), data);
+        assert_eq!(random.decrypt(&convergent.encrypt(data)?).unwrap(), data);
+        assert!(other.decrypt(&convergent.encrypt(data)?).is_err());
+        assert!(convergent.decrypt(&[]).is_err());
+        let mut tampered = convergent.encrypt(data)?;
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(convergent.decrypt(&tampered).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "xchacha20poly1305")]
+    #[test]
+    fn xchacha20poly1305() -> object_rainbow::Result<()> {
+        roundtrip::<chacha20poly1305::XChaCha20Poly1305>()
+    }
+
+    #[cfg(feature = "xchacha20poly1305")]
+    #[test]
+    fn random_nonces() -> object_rainbow::Result<()> {
+        use object_rainbow::{Fetch, FullHash, Singular};
+        use object_rainbow_point::IntoPoint;
+
+        smol::block_on(async {
+            let key = XChaCha20Poly1305Key::new([3; 32], Nonces::Random);
+            let a = crate::encrypt_point(key.clone(), (*b"alisa").point()).await?;
+            let b = crate::encrypt_point(key, (*b"alisa").point()).await?;
+            assert_ne!(a.hash(), b.hash());
+            assert_eq!(a.fetch().await?.full_hash(), a.hash());
+            assert_eq!(a.fetch().await?.full_hash(), a.hash());
+            assert_eq!(a.fetch().await?.into_inner(), *b"alisa");
+            assert_eq!(b.fetch().await?.into_inner(), *b"alisa");
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "aes-gcm-siv")]
+    #[test]
+    fn aes_gcm_siv() -> object_rainbow::Result<()> {
+        roundtrip::<aes_gcm_siv::Aes256GcmSiv>()
+    }
+
+    #[cfg(all(feature = "argon2", feature = "xchacha20poly1305"))]
+    #[test]
+    fn passphrase() -> Result<(), argon2::Error> {
+        let a = XChaCha20Poly1305Key::from_passphrase(b"hunter2", b"saltsalt", Nonces::Random)?;
+        let b = XChaCha20Poly1305Key::from_passphrase(b"hunter2", b"saltsalt", Nonces::Random)?;
+        let c = XChaCha20Poly1305Key::from_passphrase(b"hunter2", b"pepperpepper", Nonces::Random)?;
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(
+            XChaCha20Poly1305Key::from_passphrase(b"hunter2", b"salt", Nonces::Random).is_err()
+        );
+        Ok(())
+    }
+}
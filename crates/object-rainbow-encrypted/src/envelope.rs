@@ -0,0 +1,206 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use object_rainbow::{
+    ExtraFor, Hash, ListHashes, Output, Parse, PointInput, PointVisitor, Tagged, ToOutput,
+    Topological, Traversible,
+    length_prefixed::{LpBytes, LpVec},
+};
+use object_rainbow_point::Point;
+
+use crate::{Encrypted, EncryptedExtra, Key, encrypt_point};
+
+/// [`Key`] that can itself be encrypted for [`Envelope`] recipients.
+pub trait DataKey: Key {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(data: &[u8]) -> object_rainbow::Result<Self>;
+}
+
+/// Subtree encrypted with its own data key `D`, which is in turn encrypted for each recipient
+/// `K`.
+///
+/// Parsing needs any one of the recipient keys. Changing the recipients only rewrites this
+/// object, the subtree stays as-is. Removed recipients that kept the data key can still read the
+/// subtree; use [`reencrypt`] on [`Envelope::inner`] with a new data key to cut them off.
+///
+/// [`reencrypt`]: crate::reencrypt
+pub struct Envelope<K, D, T> {
+    wrapped: Arc<LpVec<LpBytes>>,
+    data_key: D,
+    inner: Point<Encrypted<D, T>>,
+    _recipient: PhantomData<fn() -> K>,
+}
+
+impl<K, D: Clone, T> Clone for Envelope<K, D, T> {
+    fn clone(&self) -> Self {
+        Self {
+            wrapped: self.wrapped.clone(),
+            data_key: self.data_key.clone(),
+            inner: self.inner.clone(),
+            _recipient: PhantomData,
+        }
+    }
+}
+
+fn wrap<K: Key>(
+    data_key: &impl DataKey,
+    recipients: &[K],
+) -> object_rainbow::Result<Arc<LpVec<LpBytes>>> {
+    let data_key = data_key.to_bytes();
+    Ok(Arc::new(LpVec(
+        recipients
+            .iter()
+            .map(|recipient| Ok(LpBytes(recipient.encrypt(&data_key)?)))
+            .collect::<object_rainbow::Result<_>>()?,
+    )))
+}
+
+impl<K: Key, D: DataKey, T: Traversible + Clone> Envelope<K, D, T> {
+    pub async fn new(data_key: D, recipients: &[K], object: T) -> object_rainbow::Result<Self> {
+        let inner = encrypt_point(data_key.clone(), Point::from_object(object)).await?;
+        Ok(Self {
+            wrapped: wrap(&data_key, recipients)?,
+            data_key,
+            inner,
+            _recipient: PhantomData,
+        })
+    }
+}
+
+impl<K: Key, D: DataKey, T> Envelope<K, D, T> {
+    /// Replace all recipients. The subtree is left untouched.
+    pub fn set_recipients(&mut self, recipients: &[K]) -> object_rainbow::Result<()> {
+        self.wrapped = wrap(&self.data_key, recipients)?;
+        Ok(())
+    }
+
+    pub fn add_recipient(&mut self, recipient: &K) -> object_rainbow::Result<()> {
+        let wrapped = recipient.encrypt(&self.data_key.to_bytes())?;
+        Arc::make_mut(&mut self.wrapped).push(LpBytes(wrapped));
+        Ok(())
+    }
+
+    pub fn recipient_count(&self) -> usize {
+        self.wrapped.len()
+    }
+
+    pub fn data_key(&self) -> &D {
+        &self.data_key
+    }
+
+    pub fn inner(&self) -> &Point<Encrypted<D, T>> {
+        &self.inner
+    }
+}
+
+impl<K, D, T> ToOutput for Envelope<K, D, T> {
+    fn to_output(&self, output: &mut impl Output) {
+        self.wrapped.to_output(output);
+        self.inner.to_output(output);
+    }
+}
+
+impl<K, D, T> ListHashes for Envelope<K, D, T> {
+    fn list_hashes(&self, f: &mut impl FnMut(Hash)) {
+        self.inner.list_hashes(f);
+    }
+
+    fn point_count(&self) -> usize {
+        1
+    }
+}
+
+impl<K: Key, D: Key, T: Traversible> Topological for Envelope<K, D, T> {
+    fn traverse(&self, visitor: &mut impl PointVisitor) {
+        self.inner.traverse(visitor);
+    }
+}
+
+impl<K, D, T> Tagged for Envelope<K, D, T> {}
+
+impl<
+    K: Key,
+    D: DataKey,
+    T: Traversible,
+    Extra: 'static + Send + Sync + Clone + ExtraFor<T>,
+    I: PointInput<Extra: EncryptedExtra<K, Extra = Extra>>,
+> Parse<I> for Envelope<K, D, T>
+{
+    fn parse(mut input: I) -> object_rainbow::Result<Self> {
+        let wrapped = input.parse_inline::<LpVec<LpBytes>>()?;
+        let (key, extra) = input.extra().parts();
+        let data_key = wrapped
+            .iter()
+            .find_map(|wrapped| key.decrypt(wrapped).ok())
+            .ok_or_else(|| object_rainbow::error_consistency!("not a recipient"))?;
+        let data_key = D::from_bytes(&data_key)?;
+        let inner = input.parse_inline_extra((data_key.clone(), extra))?;
+        input.empty()?;
+        Ok(Self {
+            wrapped: Arc::new(wrapped),
+            data_key,
+            inner,
+            _recipient: PhantomData,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "xchacha20poly1305"))]
+mod test {
+    use object_rainbow::{Fetch, Singular};
+    use object_rainbow_fetchall::fetchall;
+    use object_rainbow_point::{IntoPoint, Point};
+
+    use crate::{Envelope, Nonces, XChaCha20Poly1305Key};
+
+    type Key = XChaCha20Poly1305Key;
+    type Test = Envelope<Key, Key, (Point<[u8; 5]>, [u8; 7])>;
+
+    async fn store(point: &Point<Test>, key: Key) -> object_rainbow::Result<Point<Test>> {
+        let map = fetchall(&point.fetch().await?).await?;
+        Ok(point.with_resolve(map.to_resolve(), key))
+    }
+
+    async fn read(point: &Point<Test>, key: Key) -> object_rainbow::Result<[u8; 5]> {
+        let (alisa, _) = store(point, key)
+            .await?
+            .fetch()
+            .await?
+            .inner()
+            .fetch()
+            .await?
+            .into_inner();
+        alisa.fetch().await
+    }
+
+    #[test]
+    fn recipients() -> object_rainbow::Result<()> {
+        smol::block_on(async {
+            let alice = Key::generate(Nonces::Convergent);
+            let bob = Key::generate(Nonces::Random);
+            let carol = Key::generate(Nonces::Convergent);
+            let mut envelope = Test::new(
+                Key::generate(Nonces::Random),
+                &[alice.clone(), bob.clone()],
+                ((*b"alisa").point(), *b"feistel"),
+            )
+            .await?;
+            let point = envelope.clone().point();
+            assert_eq!(read(&point, alice.clone()).await?, *b"alisa");
+            assert_eq!(read(&point, bob.clone()).await?, *b"alisa");
+            assert!(read(&point, carol.clone()).await.is_err());
+            envelope.set_recipients(std::slice::from_ref(&bob))?;
+            envelope.add_recipient(&carol)?;
+            assert_eq!(envelope.recipient_count(), 2);
+            let rewrapped = envelope.point();
+            assert_ne!(point.hash(), rewrapped.hash());
+            assert_eq!(
+                point.fetch().await?.inner().hash(),
+                rewrapped.fetch().await?.inner().hash(),
+            );
+            assert!(read(&rewrapped, alice).await.is_err());
+            assert_eq!(read(&rewrapped, bob).await?, *b"alisa");
+            assert_eq!(read(&rewrapped, carol).await?, *b"alisa");
+            Ok(())
+        })
+    }
+}
@@ -1,3 +1,5 @@
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
 use std::{
     ops::{Deref, DerefMut},
     sync::Arc,
@@ -14,15 +16,33 @@ use object_rainbow::{
 };
 use object_rainbow_point::{ExtractResolve, Extras, Point};
 
+#[cfg(feature = "aes-gcm-siv")]
+pub use self::aead::Aes256GcmSivKey;
+#[cfg(feature = "xchacha20poly1305")]
+pub use self::aead::XChaCha20Poly1305Key;
+#[cfg(feature = "_aead")]
+pub use self::aead::{AeadKey, Nonces};
+pub use self::{
+    envelope::{DataKey, Envelope},
+    padding::{Padded, PaddedError, Padding},
+};
+
+#[cfg(feature = "_aead")]
+mod aead;
+mod envelope;
+mod padding;
+
+const MANGLE_PREFIX: &[u8] = b"this encrypted constant is followed by an unencrypted inner hash";
+
 #[derive_for_wrapped]
 pub trait Key: 'static + Sized + Send + Sync + Clone + PartialEq + Eq {
     type Error: 'static + Send + Sync + std::error::Error;
-    fn encrypt(&self, data: &[u8]) -> Vec<u8>;
+    fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>>;
     fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error>;
-    fn mangle_prefix(&self) -> Vec<u8> {
-        self.encrypt(b"this encrypted constant is followed by an unencrypted inner hash")
+    fn mangle_prefix(&self) -> object_rainbow::Result<Vec<u8>> {
+        self.encrypt(MANGLE_PREFIX)
     }
-    fn encrypt_output(&self, object: impl ToOutput) -> Vec<u8> {
+    fn encrypt_output(&self, object: impl ToOutput) -> object_rainbow::Result<Vec<u8>> {
         self.encrypt(&object.vec())
     }
 }
@@ -178,7 +198,7 @@ impl<K: Key, D: Fetch<T: Topological + Tagged>> Fetch for RawFetch<K, D> {
             let (header, _) = side_parse(&self.key, &encrypted, &resolve)?;
             let decrypted = self.decrypted.fetch().await?;
             let inner = header.with(decrypted)?;
-            Ok((Encrypted { inner }, resolve))
+            Ok((Encrypted::from_encrypted(inner, encrypted)?, resolve))
         })
     }
 
@@ -188,7 +208,7 @@ impl<K: Key, D: Fetch<T: Topological + Tagged>> Fetch for RawFetch<K, D> {
             let (header, _) = side_parse(&self.key, &encrypted, &resolve)?;
             let decrypted = self.decrypted.fetch().await?;
             let inner = header.with(decrypted)?;
-            Ok(Encrypted { inner })
+            Encrypted::from_encrypted(inner, encrypted)
         })
     }
 
@@ -204,7 +224,10 @@ impl<K: Key, D: Fetch<T: Topological + Tagged>> Fetch for RawFetch<K, D> {
             return Ok(None);
         };
         let inner = header.with(decrypted)?;
-        Ok(Some((Encrypted { inner }, resolve)))
+        Ok(Some((
+            Encrypted::from_encrypted(inner, encrypted)?,
+            resolve,
+        )))
     }
 }
 
@@ -297,7 +320,7 @@ impl<K: Key, D: Fetch<T: Topological + Tagged>> Fetch for InnerFetch<K, D> {
             let (header, _) = side_parse(&self.key, &encrypted, &resolve)?;
             let decrypted = self.decrypted.fetch().await?;
             let inner = header.with(decrypted)?;
-            Ok((Encrypted { inner }, resolve))
+            Ok((Encrypted::from_encrypted(inner, encrypted)?, resolve))
         })
     }
 
@@ -307,7 +330,7 @@ impl<K: Key, D: Fetch<T: Topological + Tagged>> Fetch for InnerFetch<K, D> {
             let (header, _) = side_parse(&self.key, &encrypted, &resolve)?;
             let decrypted = self.decrypted.fetch().await?;
             let inner = header.with(decrypted)?;
-            Ok(Encrypted { inner })
+            Encrypted::from_encrypted(inner, encrypted)
         })
     }
 
@@ -320,7 +343,10 @@ impl<K: Key, D: Fetch<T: Topological + Tagged>> Fetch for InnerFetch<K, D> {
             return Ok(None);
         };
         let inner = header.with(decrypted)?;
-        Ok(Some((Encrypted { inner }, resolve)))
+        Ok(Some((
+            Encrypted::from_encrypted(inner, encrypted)?,
+            resolve,
+        )))
     }
 }
 
@@ -381,6 +407,21 @@ impl<K: Key, T: Topological> Topological for Inner<K, T> {
 
 pub struct Encrypted<K, T> {
     inner: Inner<K, T>,
+    /// What this was parsed from, or encrypted to on construction. Keeps the hash stable with
+    /// [`Key`]s that don't encrypt deterministically.
+    encrypted: Arc<Vec<u8>>,
+    /// Hash of [`Key::mangle_prefix`].
+    mangle: Hash,
+}
+
+impl<K: Key, T> Encrypted<K, T> {
+    fn from_encrypted(inner: Inner<K, T>, encrypted: Vec<u8>) -> object_rainbow::Result<Self> {
+        Ok(Self {
+            mangle: inner.key.0.mangle_prefix()?.data_hash(),
+            inner,
+            encrypted: Arc::new(encrypted),
+        })
+    }
 }
 
 impl<K, T: Clone> Encrypted<K, T> {
@@ -401,6 +442,8 @@ impl<K: Clone, T> Clone for Encrypted<K, T> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            encrypted: self.encrypted.clone(),
+            mangle: self.mangle,
         }
     }
 }
@@ -428,11 +471,11 @@ impl<K: Key, T: Topological> Topological for Encrypted<K, T> {
 impl<K: Key, T: ToOutput> ToOutput for Encrypted<K, T> {
     fn to_output(&self, output: &mut impl object_rainbow::Output) {
         if output.is_mangling() {
-            output.write(&*self.inner.key.mangle_prefix().data_hash());
+            output.write(&*self.mangle);
             output.write(&*self.inner.decrypted.data_hash());
         }
         if output.is_real() {
-            output.write(&self.inner.key.encrypt_output(&self.inner));
+            output.write(&self.encrypted);
         }
     }
 }
@@ -470,12 +513,13 @@ impl<
     fn parse(input: I) -> object_rainbow::Result<Self> {
         let with_key = input.extra().parts();
         let resolve = input.resolve().clone();
+        let encrypted = input.parse_all()?;
         let source = with_key
             .0
-            .decrypt(&input.parse_all()?)
+            .decrypt(&encrypted)
             .map_err(object_rainbow::Error::consistency)?;
         let inner = Inner::<K, T>::parse_slice_extra(&source, &resolve, &with_key)?;
-        Ok(Self { inner })
+        Self::from_encrypted(inner, encrypted.to_vec())
     }
 }
 
@@ -524,6 +568,8 @@ pub async fn encrypt_point<K: Key, T: Traversible>(
                 key: K,
                 decrypted: F,
                 topology: Arc<LpVec<Arc<dyn Singular>>>,
+                encrypted: Arc<Vec<u8>>,
+                mangle: Hash,
             }
             impl<K: Key, F: 'static + SingularFetch<T: Traversible>> FetchFn for WithTopology<K, F> {
                 type T = Encrypted<K, F::T>;
@@ -531,13 +577,19 @@ pub async fn encrypt_point<K: Key, T: Traversible>(
                     let decrypted = self.decrypted.fetch().await?;
                     let topology = self.topology.clone();
                     let key = self.key.clone();
-                    Ok(Encrypted::from_topology(key, topology, decrypted))
+                    Ok(Encrypted {
+                        inner: Inner::new(key, topology, decrypted),
+                        encrypted: self.encrypted.clone(),
+                        mangle: self.mangle,
+                    })
                 }
             }
             WithTopology {
                 key,
                 decrypted,
                 topology,
+                encrypted: encrypted.encrypted.clone(),
+                mangle: encrypted.mangle,
             }
         }),
     );
@@ -555,19 +607,47 @@ pub async fn encrypt<K: Key, T: Traversible>(
     });
     let topology = futures_util::future::try_join_all(futures).await?;
     let topology = Arc::new(LpVec(topology));
-    Ok(Encrypted::from_topology(key, topology, decrypted))
+    Encrypted::from_topology(key, topology, decrypted)
 }
 
-impl<K: Key, T: Traversible> Encrypted<K, T> {
-    fn from_topology(key: K, topology: Arc<LpVec<Arc<dyn Singular>>>, decrypted: T) -> Self {
-        let decrypted = Arc::new(decrypted);
-        let inner = Inner {
+/// Move the graph behind `point` from `old_key` to `new_key`.
+///
+/// The plaintext is left as-is, so inner (and mangled) hashes don't change. Subtrees already
+/// encrypted with `new_key` are kept, the rest get re-encrypted concurrently.
+pub async fn reencrypt<K: Key, N: Key, T: Traversible + Clone>(
+    point: Point<Encrypted<K, T>>,
+    old_key: &K,
+    new_key: N,
+) -> object_rainbow::Result<Point<Encrypted<N, T>>> {
+    let encrypted = point.fetch().await?;
+    if encrypted.inner.key.0 != *old_key {
+        return Err(object_rainbow::error_operation!(
+            "not encrypted with the old key"
+        ));
+    }
+    encrypt_point(new_key, Point::from_object(encrypted.into_inner())).await
+}
+
+impl<K, T: Traversible> Inner<K, T> {
+    fn new(key: K, topology: Arc<LpVec<Arc<dyn Singular>>>, decrypted: T) -> Self {
+        Self {
             tags: T::HASH,
             key: Extras(key),
             topology,
-            decrypted,
-        };
-        Self { inner }
+            decrypted: Arc::new(decrypted),
+        }
+    }
+}
+
+impl<K: Key, T: Traversible> Encrypted<K, T> {
+    fn from_topology(
+        key: K,
+        topology: Arc<LpVec<Arc<dyn Singular>>>,
+        decrypted: T,
+    ) -> object_rainbow::Result<Self> {
+        let inner = Inner::new(key, topology, decrypted);
+        let encrypted = inner.key.0.encrypt_output(&inner)?;
+        Self::from_encrypted(inner, encrypted)
     }
 }
 
@@ -0,0 +1,125 @@
+use crate::Key;
+
+/// Rounding applied to lengths to hide the exact value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Padding {
+    #[default]
+    None,
+    /// Next power of two.
+    PowerOfTwo,
+    /// Next multiple of the block size. `0` means no padding.
+    Block(usize),
+}
+
+impl Padding {
+    pub fn padded_len(&self, len: usize) -> usize {
+        match *self {
+            Self::None | Self::Block(0) => len,
+            Self::PowerOfTwo => len.next_power_of_two(),
+            Self::Block(size) => len.next_multiple_of(size),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PaddedError<E> {
+    #[error(transparent)]
+    Key(E),
+    #[error("invalid padding")]
+    Padding,
+}
+
+/// Pads plaintext before handing it to `K`, so that ciphertext lengths only reveal the bucket.
+///
+/// The padding is a single `0x80` followed by zeroes, and is stripped on decryption.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Padded<K> {
+    key: K,
+    padding: Padding,
+}
+
+impl<K> Padded<K> {
+    pub fn new(key: K, padding: Padding) -> Self {
+        Self { key, padding }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+}
+
+impl<K: Key> Key for Padded<K> {
+    type Error = PaddedError<K::Error>;
+
+    fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+        let len = self.padding.padded_len(data.len() + 1);
+        let mut padded = Vec::with_capacity(len);
+        padded.extend_from_slice(data);
+        padded.push(0x80);
+        padded.resize(len, 0);
+        self.key.encrypt(&padded)
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        let mut data = self.key.decrypt(data).map_err(PaddedError::Key)?;
+        let len = data
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .filter(|&at| data[at] == 0x80)
+            .ok_or(PaddedError::Padding)?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// Not padded, so that the mangled hashes match those of `K`.
+    fn mangle_prefix(&self) -> object_rainbow::Result<Vec<u8>> {
+        self.key.mangle_prefix()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use crate::{Key, Padded, PaddedError, Padding};
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Plain;
+
+    impl Key for Plain {
+        type Error = Infallible;
+
+        fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
+            Ok(data.to_vec())
+        }
+
+        fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+            Ok(data.to_vec())
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        let pow2 = Padded::new(Plain, Padding::PowerOfTwo);
+        let block = Padded::new(Plain, Padding::Block(100));
+        for len in 0..1000 {
+            let data = vec![0x80; len];
+            let padded = pow2.encrypt(&data).unwrap();
+            assert_eq!(padded.len(), (len + 1).next_power_of_two());
+            assert_eq!(pow2.decrypt(&padded).unwrap(), data);
+            let padded = block.encrypt(&data).unwrap();
+            assert_eq!(padded.len(), (len + 1).next_multiple_of(100));
+            assert_eq!(block.decrypt(&padded).unwrap(), data);
+        }
+        assert_eq!(
+            Padded::new(Plain, Padding::None).encrypt(b"abc").unwrap(),
+            b"abc\x80",
+        );
+        assert!(matches!(pow2.decrypt(b"abc\0"), Err(PaddedError::Padding)));
+        assert!(matches!(pow2.decrypt(b""), Err(PaddedError::Padding)));
+    }
+}
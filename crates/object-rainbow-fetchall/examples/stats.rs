@@ -0,0 +1,52 @@
+use object_rainbow::{FullHash, Singular};
+use object_rainbow_fetchall::{Census, Totals, fetchall};
+use object_rainbow_point::IntoPoint;
+
+fn main() -> anyhow::Result<()> {
+    smol::block_on(async move {
+        let shared = (*b"abc").point();
+        let first = (shared.clone(), *b"de");
+        let second = (shared.clone(), (*b"fgh").point());
+        let stats = Census::new().root(&first).root(&second).run().await?;
+        // `first` is a 32-byte point and two bytes, `second` is two points
+        assert_eq!(
+            stats.total,
+            Totals {
+                objects: 4,
+                bytes: 34 + 64 + 3 + 3
+            }
+        );
+        assert_eq!(
+            stats.shared,
+            Totals {
+                objects: 1,
+                bytes: 3
+            }
+        );
+        assert_eq!(stats.deepest.len(), 2);
+        assert_eq!(
+            stats
+                .by_tags
+                .values()
+                .map(|sizes| sizes.total.objects)
+                .sum::<usize>(),
+            4,
+        );
+        let alone = Census::new().root(&second).run().await?;
+        assert_eq!(alone.shared, Totals::default());
+        assert_eq!(Totals::of(&fetchall(&second).await?), alone.total);
+
+        let deep = (shared.clone().point().point(), *b"i");
+        let stats = Census::new().root(&first).root(&deep).run().await?;
+        assert_eq!(
+            stats.deepest,
+            [
+                deep.full_hash(),
+                deep.0.hash(),
+                shared.clone().point().hash(),
+                shared.hash(),
+            ],
+        );
+        Ok(())
+    })
+}
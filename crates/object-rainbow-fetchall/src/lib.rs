@@ -11,6 +11,10 @@ use object_rainbow::{
 };
 use object_rainbow_local_map::LocalMap;
 
+pub use self::stats::{Census, Sizes, Stats, Totals};
+
+mod stats;
+
 type Dependency = Box<
     dyn 'static
         + Send
@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    pin::Pin,
+};
+
+use object_rainbow::{Hash, PointVisitor, SingularFetch, Traversible};
+use object_rainbow_local_map::LocalMap;
+
+type Fetching = Pin<Box<dyn Send + Future<Output = object_rainbow::Result<Node>>>>;
+
+/// What [`Census`] needs from an object: its size, its type, and points to descend into.
+struct Node {
+    size: usize,
+    tags: Hash,
+    children: Vec<(Hash, Fetching)>,
+}
+
+impl Node {
+    fn of<T: Traversible>(object: &T) -> Self {
+        let mut children = Children(Vec::with_capacity(object.point_count()));
+        object.traverse(&mut children);
+        Self {
+            size: object.vec().len(),
+            tags: T::HASH,
+            children: children.0,
+        }
+    }
+}
+
+struct Children(Vec<(Hash, Fetching)>);
+
+impl PointVisitor for Children {
+    fn visit(&mut self, point: &(impl 'static + SingularFetch<T: Traversible> + Clone)) {
+        let point = point.clone();
+        self.0.push((
+            point.hash(),
+            Box::pin(async move { Ok(Node::of(&point.fetch().await?)) }),
+        ));
+    }
+}
+
+/// Number and size of unique objects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+impl Totals {
+    /// Everything in `map`, without walking it. For a map returned by
+    /// [`fetchall`](crate::fetchall), that's [`Stats::total`] of its root.
+    pub fn of(map: &LocalMap) -> Self {
+        Self {
+            objects: map.len(),
+            bytes: map.values_size(),
+        }
+    }
+
+    fn add(&mut self, size: usize) {
+        self.objects += 1;
+        self.bytes += size;
+    }
+}
+
+/// Sizes of the unique objects of one type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sizes {
+    pub total: Totals,
+    /// Number of objects by the bit length of their size: `0` is for empty ones, and `n` is for
+    /// ones of at least `2^(n-1)` bytes and less than `2^n`.
+    pub buckets: BTreeMap<u32, usize>,
+}
+
+/// Outcome of a [`Census`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Objects reachable from any of the roots, each counted once.
+    pub total: Totals,
+    /// Objects reachable from more than one root.
+    pub shared: Totals,
+    /// Hashes of the longest chain of points, from one of the roots down.
+    pub deepest: Vec<Hash>,
+    /// Sizes by [`Tagged::HASH`](object_rainbow::Tagged::HASH).
+    pub by_tags: BTreeMap<Hash, Sizes>,
+}
+
+struct Reached {
+    size: usize,
+    children: Vec<Hash>,
+    /// How many roots reach it.
+    roots: usize,
+    last_root: usize,
+}
+
+/// Walks from one or more roots, through whatever [`Resolve`](object_rainbow::Resolve) their
+/// points are from, and reports on the objects reached. Each object is fetched once.
+#[derive(Default)]
+pub struct Census {
+    roots: Vec<(Hash, Node)>,
+}
+
+impl Census {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn root(mut self, object: &impl Traversible) -> Self {
+        self.roots.push((object.full_hash(), Node::of(object)));
+        self
+    }
+
+    pub async fn run(self) -> object_rainbow::Result<Stats> {
+        let mut stats = Stats::default();
+        let mut reached = HashMap::<Hash, Reached>::new();
+        let roots = self.roots.iter().map(|(hash, _)| *hash).collect::<Vec<_>>();
+        for (root, (hash, node)) in self.roots.into_iter().enumerate() {
+            let mut stack: Vec<(Hash, Option<Fetching>)> =
+                vec![(hash, Some(Box::pin(async { Ok(node) })))];
+            while let Some((hash, fetching)) = stack.pop() {
+                if let Some(reached) = reached.get_mut(&hash) {
+                    if reached.last_root == root {
+                        continue;
+                    }
+                    reached.last_root = root;
+                    reached.roots += 1;
+                    if reached.roots == 2 {
+                        stats.shared.add(reached.size);
+                    }
+                    stack.extend(reached.children.iter().map(|hash| (*hash, None)));
+                    continue;
+                }
+                let Some(fetching) = fetching else {
+                    unreachable!("only reached objects are pushed without fetching");
+                };
+                let node = fetching.await?;
+                stats.total.add(node.size);
+                let sizes = stats.by_tags.entry(node.tags).or_default();
+                sizes.total.add(node.size);
+                *sizes
+                    .buckets
+                    .entry(usize::BITS - node.size.leading_zeros())
+                    .or_default() += 1;
+                reached.insert(
+                    hash,
+                    Reached {
+                        size: node.size,
+                        children: node.children.iter().map(|(hash, _)| *hash).collect(),
+                        roots: 1,
+                        last_root: root,
+                    },
+                );
+                stack.extend(
+                    node.children
+                        .into_iter()
+                        .map(|(hash, fetching)| (hash, Some(fetching))),
+                );
+            }
+        }
+        stats.deepest = deepest(&reached, &roots);
+        Ok(stats)
+    }
+}
+
+/// Longest chain of points starting at one of `roots`.
+fn deepest(reached: &HashMap<Hash, Reached>, roots: &[Hash]) -> Vec<Hash> {
+    // height of each object, and the child it's reached through
+    let mut heights = HashMap::<Hash, (usize, Option<Hash>)>::new();
+    let mut stack = roots.iter().map(|hash| (*hash, false)).collect::<Vec<_>>();
+    while let Some((hash, expanded)) = stack.pop() {
+        if heights.contains_key(&hash) {
+            continue;
+        }
+        let children = &reached[&hash].children;
+        if expanded {
+            let height = children
+                .iter()
+                .map(|child| (heights[child].0, Some(*child)))
+                .max_by_key(|(height, _)| *height)
+                .map_or((1, None), |(height, child)| (height + 1, child));
+            heights.insert(hash, height);
+        } else {
+            stack.push((hash, true));
+            stack.extend(children.iter().map(|child| (*child, false)));
+        }
+    }
+    let mut next = roots.iter().max_by_key(|hash| heights[hash].0).copied();
+    let mut path = Vec::new();
+    while let Some(hash) = next {
+        path.push(hash);
+        next = heights[&hash].1;
+    }
+    path
+}
@@ -0,0 +1,27 @@
+use std::pin::pin;
+
+use futures_util::TryStreamExt;
+use object_rainbow_history_store::HistoryStore;
+use object_rainbow_store::MemoryStore;
+use object_rainbow_trie::TrieMap;
+
+type Store = HistoryStore<TrieMap<Vec<u8>, u8>, (Option<u8>, Vec<u8>), MemoryStore>;
+
+fn main() -> anyhow::Result<()> {
+    smol::block_on(async {
+        let store = Store::new("main", MemoryStore::new());
+        let mut trees = pin!(store.watch());
+        assert!(trees.try_next().await?.unwrap().is_empty());
+        let writer = smol::spawn({
+            let store = store.clone();
+            async move { store.commit((Some(123), b"abc".into())).await }
+        });
+        let tree = trees.try_next().await?.unwrap();
+        assert_eq!(tree.get(&b"abc".into()).await?, Some(123));
+        writer.await?;
+        store.commit((None, b"abc".into())).await?;
+        let tree = trees.try_next().await?.unwrap();
+        assert!(tree.get(&b"abc".into()).await?.is_none());
+        Ok(())
+    })
+}
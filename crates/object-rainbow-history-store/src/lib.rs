@@ -1,5 +1,6 @@
 use std::{marker::PhantomData, sync::Arc};
 
+use futures_util::{Stream, TryStreamExt};
 use object_rainbow::{Fetch, Inline, Object};
 use object_rainbow_apply::Apply;
 use object_rainbow_history::History;
@@ -66,6 +67,16 @@ impl<
             .await
     }
 
+    /// The current tree, then every tree committed after it, including by other processes.
+    pub fn watch(&self) -> impl Send + Stream<Item = object_rainbow::Result<T>> {
+        self.store
+            .watch::<History<T, D>>(&self.key)
+            .and_then(async |history| match history {
+                Some(history) => history.fetch().await?.tree().await,
+                None => Ok(T::default()),
+            })
+    }
+
     pub async fn forward(&self, other: History<T, D>) -> object_rainbow::Result<()> {
         let mut history = self
             .store
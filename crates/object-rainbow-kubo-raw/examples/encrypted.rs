@@ -12,9 +12,9 @@ struct InverseKey;
 impl Key for InverseKey {
     type Error = Infallible;
 
-    fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+    fn encrypt(&self, data: &[u8]) -> object_rainbow::Result<Vec<u8>> {
         println!("encrypting");
-        data.iter().copied().map(|x| !x).collect()
+        Ok(data.iter().copied().map(|x| !x).collect())
     }
 
     fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
@@ -0,0 +1,435 @@
+#![forbid(unsafe_code)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+#![cfg_attr(docsrs, doc(cfg_hide(doc)))]
+#![cfg(unix)]
+
+use std::{
+    ffi::OsStr,
+    fs::{File, OpenOptions, Permissions},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{MetadataExt, PermissionsExt},
+    },
+    path::Path,
+    pin::pin,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::{AsyncWriteExt, Stream, TryStreamExt, io::AllowStdIo};
+use object_rainbow::{
+    Enum, FailFuture, Fetch, FullHash, InlineOutput, ListHashes, MaybeHasNiche, Parse, ParseInline,
+    Singular, Size, Tagged, ToOutput, Topological,
+};
+use object_rainbow_cdc::{ChunkerConfig, Chunks};
+use object_rainbow_point::{IntoPoint, Point};
+use object_rainbow_trie::TrieMap;
+use rustix::{
+    fs::{AtFlags, CWD, Timestamps, UTIME_OMIT, utimensat},
+    time::Timespec,
+};
+
+#[derive(
+    ToOutput,
+    InlineOutput,
+    Tagged,
+    ListHashes,
+    Topological,
+    Parse,
+    ParseInline,
+    Size,
+    MaybeHasNiche,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub struct Metadata {
+    pub mode: u32,
+    /// Always `0` for directories.
+    pub size: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u32,
+}
+
+impl Metadata {
+    fn from_std(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            mode: metadata.mode(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec() as u32,
+        }
+    }
+
+    fn modified(&self) -> SystemTime {
+        let nsec = Duration::from_nanos(self.mtime_nsec.into());
+        if self.mtime >= 0 {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(self.mtime.unsigned_abs()) + nsec
+        } else {
+            SystemTime::UNIX_EPOCH - Duration::from_secs(self.mtime.unsigned_abs()) + nsec
+        }
+    }
+
+    fn apply(&self, path: &Path) -> object_rainbow::Result<()> {
+        File::open(path)?.set_modified(self.modified())?;
+        std::fs::set_permissions(path, Permissions::from_mode(self.mode & 0o7777))?;
+        Ok(())
+    }
+
+    /// Set the mtime of the symlink at `path` itself. Its mode can't be changed.
+    fn apply_symlink(&self, path: &Path) -> object_rainbow::Result<()> {
+        let times = Timestamps {
+            last_access: Timespec {
+                tv_sec: 0,
+                tv_nsec: UTIME_OMIT,
+            },
+            last_modification: Timespec {
+                tv_sec: self.mtime,
+                tv_nsec: self.mtime_nsec.into(),
+            },
+        };
+        utimensat(CWD, path, &times, AtFlags::SYMLINK_NOFOLLOW).map_err(std::io::Error::from)?;
+        Ok(())
+    }
+}
+
+#[derive(
+    Enum,
+    ToOutput,
+    InlineOutput,
+    Tagged,
+    ListHashes,
+    Topological,
+    Parse,
+    ParseInline,
+    MaybeHasNiche,
+    Clone,
+    Debug,
+)]
+pub enum Entry {
+    File {
+        metadata: Metadata,
+        chunks: Point<Chunks>,
+    },
+    Directory {
+        metadata: Metadata,
+        #[tags(skip)]
+        directory: Point<Directory>,
+    },
+    Symlink {
+        metadata: Metadata,
+        target: Point<Vec<u8>>,
+    },
+}
+
+#[derive(ToOutput, InlineOutput, Tagged, ListHashes, Topological, Parse, ParseInline, Default)]
+pub struct Directory {
+    entries: TrieMap<Vec<u8>, Entry>,
+}
+
+impl Clone for Directory {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl Directory {
+    pub async fn get(&self, name: &[u8]) -> object_rainbow::Result<Option<Entry>> {
+        self.entries.get(&name.to_vec()).await
+    }
+
+    pub async fn insert(
+        &mut self,
+        name: &[u8],
+        entry: Entry,
+    ) -> object_rainbow::Result<Option<Entry>> {
+        check_name(name)?;
+        self.entries.insert(&name.to_vec(), entry).await
+    }
+
+    pub async fn remove(&mut self, name: &[u8]) -> object_rainbow::Result<Option<Entry>> {
+        self.entries.remove(&name.to_vec()).await
+    }
+
+    pub fn entries(&self) -> impl Send + Stream<Item = object_rainbow::Result<(Vec<u8>, Entry)>> {
+        self.entries.prefix_stream(&[])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn check_name(name: &[u8]) -> object_rainbow::Result<()> {
+    if name.is_empty() || name == b"." || name == b".." || name.contains(&b'/') || name.contains(&0)
+    {
+        Err(object_rainbow::error_consistency!(
+            "invalid entry name {:?}",
+            String::from_utf8_lossy(name),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Snapshot the directory at `path`.
+///
+/// Files whose [`Metadata`] matches the corresponding entry in `previous`, chunked with the same
+/// `config`, are not read again, their [`Chunks`] get reused as-is. Subdirectories that end up unchanged keep the
+/// [`Point`] from `previous`.
+pub async fn snapshot(
+    path: impl AsRef<Path>,
+    config: ChunkerConfig,
+    previous: &Directory,
+) -> object_rainbow::Result<Directory> {
+    snapshot_directory(path.as_ref(), config, previous).await
+}
+
+fn snapshot_directory<'a>(
+    path: &'a Path,
+    config: ChunkerConfig,
+    previous: &'a Directory,
+) -> FailFuture<'a, Directory> {
+    Box::pin(async move {
+        let mut names = std::fs::read_dir(path)?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        names.sort();
+        let mut directory = Directory::default();
+        for name in names {
+            let path = path.join(&name);
+            let name = name.as_bytes();
+            let std_metadata = std::fs::symlink_metadata(&path)?;
+            let metadata = Metadata::from_std(&std_metadata);
+            let file_type = std_metadata.file_type();
+            let previous = previous.get(name).await?;
+            let entry = if file_type.is_dir() {
+                let previous = match previous {
+                    Some(Entry::Directory { directory, .. }) => Some(directory),
+                    _ => None,
+                };
+                let inner = match &previous {
+                    Some(point) => point.fetch().await?,
+                    None => Directory::default(),
+                };
+                let inner = snapshot_directory(&path, config, &inner).await?;
+                let directory = match previous {
+                    Some(point) if point.hash() == inner.full_hash() => point,
+                    _ => inner.point(),
+                };
+                Entry::Directory {
+                    metadata,
+                    directory,
+                }
+            } else if file_type.is_file() {
+                let previous = match previous {
+                    Some(Entry::File {
+                        metadata: previous_metadata,
+                        chunks,
+                    }) if previous_metadata == metadata => Some(chunks),
+                    _ => None,
+                };
+                let chunks = match previous {
+                    Some(chunks) if chunks.fetch().await?.config() == config => chunks,
+                    _ => {
+                        let source = AllowStdIo::new(File::open(&path)?);
+                        Chunks::in_memory(config, source, async |f| f())
+                            .await?
+                            .point()
+                    }
+                };
+                Entry::File { metadata, chunks }
+            } else if file_type.is_symlink() {
+                let target = std::fs::read_link(&path)?;
+                Entry::Symlink {
+                    metadata,
+                    target: target.as_os_str().as_bytes().to_vec().point(),
+                }
+            } else {
+                continue;
+            };
+            directory.insert(name, entry).await?;
+        }
+        Ok(directory)
+    })
+}
+
+/// Write out `root` into `path`, creating it if it doesn't exist.
+///
+/// Whatever is in the way of an entry gets replaced, and whatever isn't in `root` gets removed.
+/// Symlinks already under `path` are never followed, so nothing outside of `path` gets written to
+/// or removed.
+pub async fn restore(root: &Directory, path: impl AsRef<Path>) -> object_rainbow::Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    restore_directory(root, path).await
+}
+
+/// Remove what's at `path`, unless it's a directory and `directory` is set. Returns whether a
+/// directory was kept.
+fn clear(path: &Path, directory: bool) -> std::io::Result<bool> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            if directory {
+                return Ok(true);
+            }
+            std::fs::remove_dir_all(path)?;
+        }
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    Ok(false)
+}
+
+fn restore_directory<'a>(directory: &'a Directory, path: &'a Path) -> FailFuture<'a, ()> {
+    Box::pin(async move {
+        let mut entries = pin!(directory.entries());
+        while let Some((name, entry)) = entries.try_next().await? {
+            check_name(&name)?;
+            let path = path.join(OsStr::from_bytes(&name));
+            match entry {
+                Entry::File { metadata, chunks } => {
+                    let chunks = chunks.fetch().await?;
+                    if chunks.len()? as u64 != metadata.size {
+                        return Err(object_rainbow::error_consistency!("file size mismatch"));
+                    }
+                    clear(&path, false)?;
+                    // fails instead of following a symlink created since
+                    let file = OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)?;
+                    let mut file = AllowStdIo::new(file);
+                    let mut data = pin!(chunks.as_stream());
+                    while let Some(data) = data.try_next().await? {
+                        file.write_all(&data).await?;
+                    }
+                    file.flush().await?;
+                    metadata.apply(&path)?;
+                }
+                Entry::Directory {
+                    metadata,
+                    directory,
+                } => {
+                    if !clear(&path, true)? {
+                        std::fs::create_dir(&path)?;
+                    }
+                    restore_directory(&directory.fetch().await?, &path).await?;
+                    metadata.apply(&path)?;
+                }
+                Entry::Symlink { metadata, target } => {
+                    let target = target.fetch().await?;
+                    clear(&path, false)?;
+                    std::os::unix::fs::symlink(OsStr::from_bytes(&target), &path)?;
+                    metadata.apply_symlink(&path)?;
+                }
+            }
+        }
+        let names = std::fs::read_dir(path)?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        for name in names {
+            if directory.get(name.as_bytes()).await?.is_none() {
+                clear(&path.join(name), false)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use macro_rules_attribute::apply;
+    use object_rainbow::{Fetch, FullHash, Singular};
+    use object_rainbow_cdc::{ChunkerConfig, FixedSize};
+    use smol_macros::test;
+
+    use crate::{Directory, Entry, restore, snapshot};
+
+    fn read(path: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        let mut stack = vec![path.to_path_buf()];
+        while let Some(dir) = stack.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let entry = entry.unwrap().path();
+                let relative = entry.strip_prefix(path).unwrap().to_path_buf();
+                if entry.is_symlink() {
+                    let target = std::fs::read_link(&entry).unwrap();
+                    files.push((relative, target.into_os_string().into_encoded_bytes()));
+                } else if entry.is_dir() {
+                    stack.push(entry);
+                } else {
+                    files.push((relative, std::fs::read(&entry).unwrap()));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[apply(test!)]
+    async fn roundtrip() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source");
+        let target = dir.path().join("target");
+        std::fs::create_dir_all(source.join("a/b"))?;
+        std::fs::create_dir_all(source.join("c"))?;
+        std::fs::write(source.join("a/b/big"), b"0123456789".repeat(1000))?;
+        std::fs::write(source.join("a/small"), b"small")?;
+        std::fs::write(source.join("c/empty"), b"")?;
+        std::fs::write(source.join("c/gone"), b"gone")?;
+        std::os::unix::fs::symlink("a/small", source.join("link"))?;
+        let config = ChunkerConfig::FixedSize(FixedSize { size: 1024 });
+        let first = snapshot(&source, config, &Directory::default()).await?;
+        restore(&first, &target).await?;
+        assert_eq!(read(&source), read(&target));
+        assert_eq!(
+            std::fs::symlink_metadata(source.join("link"))?.modified()?,
+            std::fs::symlink_metadata(target.join("link"))?.modified()?,
+        );
+        std::fs::write(source.join("c/empty"), b"not empty")?;
+        std::fs::remove_file(source.join("c/gone"))?;
+        let second = snapshot(&source, config, &first).await?;
+        let Some(Entry::Directory { directory: a0, .. }) = first.get(b"a").await? else {
+            panic!()
+        };
+        let Some(Entry::Directory { directory: a1, .. }) = second.get(b"a").await? else {
+            panic!()
+        };
+        assert_eq!(a0.hash(), a1.hash());
+        assert_ne!(first.full_hash(), second.full_hash());
+        assert!(a1.fetch().await?.get(b"small").await?.is_some());
+        // restoring over the first snapshot, with symlinks pointing outside in the way
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&outside)?;
+        std::fs::remove_dir_all(target.join("c"))?;
+        std::os::unix::fs::symlink(&outside, target.join("c"))?;
+        std::fs::remove_file(target.join("a/small"))?;
+        std::os::unix::fs::symlink(outside.join("small"), target.join("a/small"))?;
+        std::fs::create_dir_all(target.join("a/extra/nested"))?;
+        std::os::unix::fs::symlink(&outside, target.join("a/b/extra"))?;
+        std::fs::write(outside.join("kept"), b"kept")?;
+        restore(&second, &target).await?;
+        assert_eq!(read(&source), read(&target));
+        assert!(!target.join("c/gone").exists());
+        assert!(outside.join("kept").exists());
+        std::fs::remove_file(outside.join("kept"))?;
+        assert_eq!(std::fs::read_dir(&outside)?.count(), 0);
+        let refixed = ChunkerConfig::FixedSize(FixedSize { size: 100 });
+        let third = snapshot(&source, refixed, &second).await?;
+        let Some(Entry::Directory { directory: a2, .. }) = third.get(b"a").await? else {
+            panic!()
+        };
+        let Some(Entry::File { chunks, .. }) = a2.fetch().await?.get(b"small").await? else {
+            panic!()
+        };
+        assert_eq!(chunks.fetch().await?.config(), refixed);
+        Ok(())
+    }
+}
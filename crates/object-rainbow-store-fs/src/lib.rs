@@ -0,0 +1,763 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+
+use futures_util::{Stream, TryStreamExt};
+use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash};
+use object_rainbow_store::{RainbowStore, RainbowStoreMut, RefUpdate};
+
+use self::pack::Packs;
+
+mod pack;
+
+/// [`RainbowStore`] keeping each object in its own file under `objects/ab/cd/<hash>`, and each
+/// ref in a file under `refs/`.
+///
+/// Files are written to a temporary name first and then renamed into place, so a crash never
+/// leaves a partially written object or ref behind. Object files hold the diff hash followed by
+/// the data, which lets reads verify the hash. Objects copied in with [`RainbowStore::save_raw`]
+/// come without their diff hash, so they are kept as is under `raw/ab/cd/<hash>`, and are neither
+/// verified nor packed.
+///
+/// New objects are always written loose. [`FsStore::repack`] consolidates them into a pack file
+/// under `packs/`, alongside a sorted index, much like git does. Repacking and deleting take a
+/// `packs/.lock` file for the duration, and fail if another process holds it.
+///
+/// Ref updates with an expected old value take a `.<name>.lock` file next to the ref. A lock left
+/// behind by a crashed process has to be removed by hand. [`RainbowStoreMut::update_refs`] holds
+/// the locks of all its refs at once, but moves them into place one by one.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    root: Arc<Path>,
+    sync: bool,
+    packs: Arc<Packs>,
+}
+
+impl PartialEq for FsStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.sync == other.sync
+    }
+}
+
+impl Eq for FsStore {}
+
+impl FsStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().into(),
+            sync: false,
+            packs: Default::default(),
+        }
+    }
+
+    /// Also `fsync` files and their directories before a write is considered done.
+    pub fn with_sync(self, sync: bool) -> Self {
+        Self { sync, ..self }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn object_path(&self, hash: Hash) -> PathBuf {
+        let name = hex::encode(hash);
+        self.root
+            .join("objects")
+            .join(&name[..2])
+            .join(&name[2..4])
+            .join(name)
+    }
+
+    fn raw_path(&self, hash: Hash) -> PathBuf {
+        let name = hex::encode(hash);
+        self.root
+            .join("raw")
+            .join(&name[..2])
+            .join(&name[2..4])
+            .join(name)
+    }
+
+    fn contains_blocking(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        Ok(self.object_path(hash).try_exists()?
+            || self.packed_contains(hash)?
+            || self.raw_path(hash).try_exists()?)
+    }
+
+    /// Write `data` to `path`, unless `hash` is already stored in any form.
+    fn save_blocking(&self, hash: Hash, path: PathBuf, data: &[u8]) -> object_rainbow::Result<()> {
+        if self.object_path(hash).exists()
+            || self.packs.cached(hash)
+            || self.raw_path(hash).exists()
+        {
+            return Ok(());
+        }
+        write_atomic(&path, data, self.sync)
+    }
+
+    fn ref_path(&self, key: &str) -> object_rainbow::Result<PathBuf> {
+        if key
+            .split('/')
+            .any(|part| part.is_empty() || part.starts_with('.'))
+        {
+            return Err(object_rainbow::error_operation!("invalid ref name {key:?}"));
+        }
+        Ok(self.root.join("refs").join(key))
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .expect("paths are built from non-empty names");
+    let mut sibling = std::ffi::OsString::from(".");
+    sibling.push(name);
+    sibling.push(suffix);
+    path.with_file_name(sibling)
+}
+
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn create(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Run `f` on `file` at `temp`, then rename it to `path`. `temp` is removed if anything fails.
+fn replace(
+    path: &Path,
+    temp: &Path,
+    mut file: File,
+    sync: bool,
+    f: impl FnOnce(&mut File) -> object_rainbow::Result<()>,
+) -> object_rainbow::Result<()> {
+    let result = f(&mut file).and_then(|()| {
+        if sync {
+            file.sync_all()?;
+        }
+        std::fs::rename(temp, path)?;
+        if sync {
+            sync_dir(path.parent().expect("paths are built under the root"))?;
+        }
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(temp);
+    }
+    result
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    sibling(
+        path,
+        &format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ),
+    )
+}
+
+fn write_atomic(path: &Path, data: &[u8], sync: bool) -> object_rainbow::Result<()> {
+    std::fs::create_dir_all(path.parent().expect("paths are built under the root"))?;
+    let temp = temp_path(path);
+    let file = create(&temp)?;
+    replace(path, &temp, file, sync, |file| Ok(file.write_all(data)?))
+}
+
+fn read_ref(path: &Path) -> object_rainbow::Result<OptionalHash> {
+    match std::fs::read(path) {
+        Ok(value) => OptionalHash::parse_slice_refless(&value),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(OptionalHash::NONE),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn lock(key: &str, path: &Path) -> object_rainbow::Result<(PathBuf, File)> {
+    let lock = sibling(path, ".lock");
+    let file = create(&lock).map_err(|e| {
+        if e.kind() == ErrorKind::AlreadyExists {
+            object_rainbow::error_consistency!("ref {key:?} is locked")
+        } else {
+            e.into()
+        }
+    })?;
+    Ok((lock, file))
+}
+
+fn check_ref(key: &str, path: &Path, old: OptionalHash) -> object_rainbow::Result<()> {
+    if read_ref(path)? != old {
+        return Err(RefUpdate::changed(key));
+    }
+    Ok(())
+}
+
+fn compare_and_swap(
+    key: &str,
+    path: &Path,
+    old: OptionalHash,
+    hash: Hash,
+    sync: bool,
+) -> object_rainbow::Result<()> {
+    std::fs::create_dir_all(path.parent().expect("paths are built under the root"))?;
+    let (lock, file) = lock(key, path)?;
+    replace(path, &lock, file, sync, |file| {
+        check_ref(key, path, old)?;
+        Ok(file.write_all(hash.as_slice())?)
+    })
+}
+
+fn remove(path: &Path, sync: bool) -> object_rainbow::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        result => result?,
+    }
+    if sync {
+        sync_dir(path.parent().expect("paths are built under the root"))?;
+    }
+    Ok(())
+}
+
+fn delete_ref(
+    key: &str,
+    path: &Path,
+    old: Option<OptionalHash>,
+    sync: bool,
+) -> object_rainbow::Result<()> {
+    let Some(old) = old else {
+        return remove(path, sync);
+    };
+    if !path
+        .parent()
+        .expect("paths are built under the root")
+        .try_exists()?
+    {
+        return check_ref(key, path, old);
+    }
+    let (lock, file) = lock(key, path)?;
+    drop(file);
+    let result = check_ref(key, path, old).and_then(|()| remove(path, sync));
+    std::fs::remove_file(lock)?;
+    result
+}
+
+/// Take the locks of all refs in `updates`, check them, and write new values into the locks.
+fn lock_refs(
+    updates: &[(RefUpdate, PathBuf)],
+    locks: &mut Vec<(PathBuf, File)>,
+    sync: bool,
+) -> object_rainbow::Result<()> {
+    for (RefUpdate { key, .. }, path) in updates {
+        std::fs::create_dir_all(path.parent().expect("paths are built under the root"))?;
+        locks.push(lock(key, path)?);
+    }
+    let mut conflicts = Vec::new();
+    for (RefUpdate { key, old, .. }, path) in updates {
+        if let Some(old) = *old
+            && read_ref(path)? != old
+        {
+            conflicts.push(key);
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(RefUpdate::conflict_error(&conflicts));
+    }
+    for ((RefUpdate { hash, .. }, _), (_, file)) in updates.iter().zip(locks.iter_mut()) {
+        file.write_all(hash.as_slice())?;
+        if sync {
+            file.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+fn update_refs(updates: &[(RefUpdate, PathBuf)], sync: bool) -> object_rainbow::Result<()> {
+    let mut locks = Vec::with_capacity(updates.len());
+    let mut renamed = 0;
+    let result = lock_refs(updates, &mut locks, sync).and_then(|()| {
+        for ((_, path), (lock, _)) in updates.iter().zip(&locks) {
+            std::fs::rename(lock, path)?;
+            renamed += 1;
+        }
+        Ok(())
+    });
+    for (lock, _) in &locks[renamed..] {
+        let _ = std::fs::remove_file(lock);
+    }
+    result?;
+    if sync {
+        for (_, path) in updates {
+            sync_dir(path.parent().expect("paths are built under the root"))?;
+        }
+    }
+    Ok(())
+}
+
+fn list_refs(root: &Path, prefix: &str) -> object_rainbow::Result<Vec<(String, Hash)>> {
+    let mut refs = Vec::new();
+    let mut stack = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, base)) = stack.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let key = format!("{base}{name}");
+            if entry.file_type()?.is_dir() {
+                if prefix.starts_with(&key) || key.starts_with(prefix) {
+                    stack.push((entry.path(), format!("{key}/")));
+                }
+            } else if key.starts_with(prefix)
+                && let Some(hash) = read_ref(&entry.path())?.get()
+            {
+                refs.push((key, hash));
+            }
+        }
+    }
+    refs.sort();
+    Ok(refs)
+}
+
+impl RainbowStore for FsStore {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        let hash = wh.data_hash();
+        let data = wh.vec();
+        let store = self.clone();
+        blocking::unblock(move || store.save_blocking(hash, store.object_path(hash), &data)).await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        let store = self.clone();
+        blocking::unblock(move || store.contains_blocking(hash)).await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Vec<u8>> {
+        let store = self.clone();
+        blocking::unblock(move || {
+            let mut data = match std::fs::read(store.object_path(hash)) {
+                Ok(data) => data,
+                Err(e) if e.kind() == ErrorKind::NotFound => match store.packed_read(hash)? {
+                    Some(data) => data,
+                    None => {
+                        return match std::fs::read(store.raw_path(hash)) {
+                            Ok(data) => Ok(data),
+                            Err(e) if e.kind() == ErrorKind::NotFound => {
+                                Err(object_rainbow::Error::HashNotFound)
+                            }
+                            Err(e) => Err(e.into()),
+                        };
+                    }
+                },
+                Err(e) => return Err(e.into()),
+            };
+            if data.as_slice().data_hash() != hash {
+                return Err(object_rainbow::error_consistency!("hash mismatch"));
+            }
+            data.drain(..object_rainbow::HASH_SIZE);
+            Ok(data)
+        })
+        .await
+    }
+
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        let store = self.clone();
+        futures_util::stream::once(blocking::unblock(move || store.objects_blocking()))
+            .map_ok(|objects| futures_util::stream::iter(objects.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Packs holding any of `hashes` are rewritten without them.
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        let store = self.clone();
+        let hashes = hashes.to_vec();
+        blocking::unblock(move || store.delete_blocking(&hashes)).await
+    }
+
+    async fn save_raw(&self, hash: Hash, data: &[u8]) -> object_rainbow::Result<()> {
+        let data = data.to_vec();
+        let store = self.clone();
+        blocking::unblock(move || store.save_blocking(hash, store.raw_path(hash), &data)).await
+    }
+
+    /// Checked within one blocking call.
+    async fn contains_many(&self, hashes: &[Hash]) -> object_rainbow::Result<Vec<bool>> {
+        let store = self.clone();
+        let hashes = hashes.to_vec();
+        blocking::unblock(move || {
+            hashes
+                .into_iter()
+                .map(|hash| store.contains_blocking(hash))
+                .collect()
+        })
+        .await
+    }
+
+    /// Written within one blocking call.
+    async fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> object_rainbow::Result<()> {
+        let objects = objects
+            .into_iter()
+            .map(|wh| (wh.data_hash(), wh.vec()))
+            .collect::<Vec<_>>();
+        let store = self.clone();
+        blocking::unblock(move || {
+            for (hash, data) in objects {
+                store.save_blocking(hash, store.object_path(hash), &data)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl RainbowStoreMut for FsStore {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        let path = self.ref_path(key)?;
+        let key = key.to_owned();
+        let sync = self.sync;
+        blocking::unblock(move || match old {
+            Some(old) => compare_and_swap(&key, &path, old, hash, sync),
+            None => write_atomic(&path, hash.as_slice(), sync),
+        })
+        .await
+    }
+
+    /// Takes the locks of all refs before checking any of them, so concurrent writers can't
+    /// interleave. Readers may still see some of the refs updated before the rest.
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        let updates = updates
+            .into_iter()
+            .map(|update| {
+                let path = self.ref_path(&update.key)?;
+                Ok((update, path))
+            })
+            .collect::<object_rainbow::Result<Vec<_>>>()?;
+        let sync = self.sync;
+        blocking::unblock(move || update_refs(&updates, sync)).await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        let path = self.ref_path(key)?;
+        blocking::unblock(move || read_ref(&path)).await
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        let path = self.ref_path(key)?;
+        Ok(blocking::unblock(move || path.try_exists()).await?)
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        let root = self.root.join("refs");
+        let prefix = prefix.to_owned();
+        futures_util::stream::once(blocking::unblock(move || list_refs(&root, &prefix)))
+            .map_ok(|refs| futures_util::stream::iter(refs.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        let path = self.ref_path(key)?;
+        let key = key.to_owned();
+        let sync = self.sync;
+        blocking::unblock(move || delete_ref(&key, &path, old, sync)).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{pin::pin, time::Duration};
+
+    use futures_util::TryStreamExt;
+    use macro_rules_attribute::apply;
+    use object_rainbow::{Fetch, FullHash, OptionalHash, Singular};
+    use object_rainbow_point::{IntoPoint, Point};
+    use object_rainbow_store::{
+        MemoryStore, RainbowStore, RainbowStoreMut, RefUpdate, ReflogStore, Scrub, StoreMut,
+        TieredStore, poll_ref,
+    };
+    use smol_macros::test;
+
+    use crate::FsStore;
+
+    #[apply(test!)]
+    async fn objects() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("objects");
+        let store = FsStore::new(&root).with_sync(true);
+        let point = ((*b"abc").point(), *b"def").point();
+        store.save_point(&point).await?;
+        assert!(store.contains(point.hash()).await?);
+        let name = hex::encode(point.hash());
+        let path = root
+            .join("objects")
+            .join(&name[..2])
+            .join(&name[2..4])
+            .join(&name);
+        assert!(path.exists());
+        let loaded = store.point::<(Point<[u8; 3]>, [u8; 3])>(point.hash());
+        let (abc, def) = loaded.fetch().await?;
+        assert_eq!(abc.fetch().await?, *b"abc");
+        assert_eq!(def, *b"def");
+        let mut data = std::fs::read(&path)?;
+        *data.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, data)?;
+        assert!(store.fetch(point.hash()).await.is_err());
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn refs() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("refs");
+        let store = FsStore::new(&root);
+        let a = (*b"a").point();
+        let b = (*b"b").point();
+        store.save_point(&a).await?;
+        store.save_point(&b).await?;
+        assert_eq!(store.fetch_ref("heads/main").await?, OptionalHash::NONE);
+        store
+            .update_ref("heads/main", Some(OptionalHash::NONE), a.hash())
+            .await?;
+        assert!(
+            store
+                .update_ref("heads/main", Some(OptionalHash::NONE), b.hash())
+                .await
+                .is_err()
+        );
+        store
+            .update_ref("heads/main", Some(a.hash().into()), b.hash())
+            .await?;
+        assert_eq!(store.fetch_ref("heads/main").await?, b.hash());
+        std::fs::write(root.join("refs/heads/.main.lock"), b"")?;
+        assert!(
+            store
+                .update_ref("heads/main", Some(b.hash().into()), a.hash())
+                .await
+                .is_err()
+        );
+        store.update_ref("heads/main", None, a.hash()).await?;
+        assert_eq!(store.fetch_ref("heads/main").await?, a.hash());
+        for key in ["", "../main", "heads/.main.lock", "heads//main"] {
+            assert!(store.ref_exists(key).await.is_err());
+        }
+        let stored = StoreMut::new(store.clone())
+            .init("test", (*b"c").point())
+            .await?;
+        let loaded = StoreMut::new(store).load::<[u8; 1], _>("test").await?;
+        assert_eq!(loaded.hash(), stored.hash());
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn list_delete_rename() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("list_delete_rename");
+        let store = FsStore::new(&root);
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        store.update_ref("heads/main", None, a).await?;
+        store.update_ref("heads/dev", None, b).await?;
+        store.update_ref("headless", None, b).await?;
+        store.update_ref("tags/v1", None, a).await?;
+        assert_eq!(
+            store.list_refs("heads/").try_collect::<Vec<_>>().await?,
+            [("heads/dev".into(), b), ("heads/main".into(), a)],
+        );
+        assert_eq!(
+            store.list_refs("head").try_collect::<Vec<_>>().await?.len(),
+            3
+        );
+        assert_eq!(store.list_refs("").try_collect::<Vec<_>>().await?.len(), 4);
+        assert!(store.delete_ref("tags/v1", Some(b.into())).await.is_err());
+        store.delete_ref("tags/v1", Some(a.into())).await?;
+        store.delete_ref("tags/v1", None).await?;
+        store
+            .delete_ref("tags/v2", Some(OptionalHash::NONE))
+            .await?;
+        store
+            .delete_ref("missing/v2", Some(OptionalHash::NONE))
+            .await?;
+        assert!(!store.ref_exists("tags/v1").await?);
+        assert!(store.rename_ref("heads/dev", "heads/main").await.is_err());
+        store.rename_ref("heads/dev", "heads/feature").await?;
+        assert_eq!(store.fetch_ref("heads/dev").await?, OptionalHash::NONE);
+        assert_eq!(store.fetch_ref("heads/feature").await?, b);
+        assert!(store.rename_ref("heads/dev", "heads/other").await.is_err());
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn watch() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("watch");
+        let store = FsStore::new(&root);
+        let a = b"a".full_hash();
+        let mut hashes = pin!(poll_ref(&store, "main", Duration::from_millis(10)));
+        assert_eq!(hashes.try_next().await?, Some(OptionalHash::NONE));
+        FsStore::new(&root).update_ref("main", None, a).await?;
+        assert_eq!(hashes.try_next().await?, Some(a.into()));
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn transaction() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("transaction");
+        let store = StoreMut::new(FsStore::new(&root).with_sync(true));
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        let mut transaction = store.transaction();
+        transaction
+            .update("table", Some(OptionalHash::NONE), a)
+            .update("indices/table", Some(OptionalHash::NONE), a);
+        transaction.commit().await?;
+        let mut transaction = store.transaction();
+        transaction
+            .update("table", Some(a.into()), b)
+            .update("indices/table", Some(b.into()), b);
+        let error = transaction.commit().await.unwrap_err().to_string();
+        assert!(error.contains("indices/table") && !error.contains("\"table"));
+        let mut transaction = store.transaction();
+        transaction
+            .update("table", Some(a.into()), b)
+            .update("table", None, b);
+        assert!(transaction.commit().await.is_err());
+        let store = FsStore::new(&root);
+        assert_eq!(store.fetch_ref("table").await?, a);
+        assert!(!root.join("refs/.table.lock").exists());
+        let mut transaction = StoreMut::new(store.clone()).transaction();
+        transaction
+            .update("table", Some(a.into()), b)
+            .update("indices/table", None, b);
+        transaction.commit().await?;
+        assert_eq!(store.fetch_ref("indices/table").await?, b);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn reflog_locked() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("reflog_locked");
+        let store = FsStore::new(&root);
+        let reflog = ReflogStore::new(store.clone());
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        reflog.update_ref("main", None, a).await?;
+        let conflict = store
+            .update_ref("refs/main", Some(b.into()), a)
+            .await
+            .unwrap_err();
+        assert!(RefUpdate::is_conflict(&conflict));
+        std::fs::write(root.join("refs/refs/.main.lock"), b"")?;
+        // a stale lock isn't a conflict, so it fails right away instead of being retried
+        let locked = reflog.update_ref("main", None, b).await.unwrap_err();
+        assert!(!RefUpdate::is_conflict(&locked));
+        assert!(reflog.delete_ref("main", None).await.is_err());
+        assert_eq!(reflog.fetch_ref("main").await?, a);
+        std::fs::remove_file(root.join("refs/refs/.main.lock"))?;
+        reflog.update_ref("main", None, b).await?;
+        assert_eq!(reflog.reflog("main").await?.len(), 2);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn raw_and_batches() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("raw_and_batches");
+        let store = FsStore::new(&root);
+        let memory = MemoryStore::default();
+        let point = (*b"raw").point();
+        memory.save_point(&point).await?;
+        let data = memory.fetch(point.hash()).await?;
+        store.save_raw(point.hash(), &data).await?;
+        assert_eq!(store.fetch(point.hash()).await?, *data);
+        let loaded = store.point::<[u8; 3]>(point.hash());
+        assert_eq!(loaded.fetch().await?, *b"raw");
+        let saved = (*b"abc").point();
+        let with_hash = (*b"abc").with_hash();
+        store.save_many(vec![with_hash]).await?;
+        assert_eq!(
+            store
+                .contains_many(&[point.hash(), saved.hash(), b"missing".full_hash()])
+                .await?,
+            [true, true, false],
+        );
+        assert_eq!(store.list_objects().try_collect::<Vec<_>>().await?.len(), 2);
+        store.repack().await?;
+        assert_eq!(store.fetch(point.hash()).await?, *data);
+        store.delete(&[point.hash()]).await?;
+        assert!(!store.contains(point.hash()).await?);
+        assert!(store.contains(saved.hash()).await?);
+        let tiered = TieredStore::new(FsStore::new(dir.path().join("fast")), memory);
+        let point = ((*b"fast").point(), *b"tier").point();
+        tiered.save_point(&point).await?;
+        assert!(tiered.fast().contains(point.hash()).await?);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn scrub_repair() -> object_rainbow::Result<()> {
+        type Tree = (Point<[u8; 3]>, Point<[u8; 3]>);
+        let dir = tempfile::tempdir()?;
+        let store = FsStore::new(dir.path());
+        let replica = MemoryStore::default();
+        let tree = ((*b"abc").point(), (*b"def").point()).point();
+        replica.save_point(&tree).await?;
+        StoreMut::new(store.clone())
+            .init("main", tree.clone())
+            .await?;
+        let (abc, _) = tree.fetch().await?;
+        let name = hex::encode(abc.hash());
+        let path = dir
+            .path()
+            .join("objects")
+            .join(&name[..2])
+            .join(&name[2..4])
+            .join(&name);
+        let mut data = std::fs::read(&path)?;
+        *data.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, data)?;
+        let scrub = || Scrub::new(store.clone()).refs::<Tree>("");
+        let report = scrub().with_replica(replica).run().await?;
+        assert_eq!(report.damaged.len(), 1);
+        assert_eq!(report.damaged[0].referrer, Some(tree.hash()));
+        assert!(report.damaged[0].repaired);
+        assert!(scrub().run().await?.is_clean());
+        Ok(())
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use hex::FromHex;
+use object_rainbow::{HASH_SIZE, Hash, ParseSliceRefless, ToOutput, numeric::Le};
+
+use crate::{FsStore, create, replace, temp_path, write_atomic};
+
+const ENTRY_SIZE: usize = HASH_SIZE + 16;
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    hash: Hash,
+    offset: u64,
+    len: u64,
+}
+
+/// `pack-<id>.pack` with its objects back to back, described by `pack-<id>.idx` with entries
+/// sorted by hash.
+#[derive(Debug)]
+struct Pack {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Pack {
+    fn load(index: &Path) -> object_rainbow::Result<Self> {
+        let data = std::fs::read(index)?;
+        if !data.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(object_rainbow::error_consistency!(
+                "malformed pack index {index:?}"
+            ));
+        }
+        let entries = data
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let (hash, Le(offset), Le(len)) = ParseSliceRefless::parse_slice_refless(entry)?;
+                Ok(Entry { hash, offset, len })
+            })
+            .collect::<object_rainbow::Result<Vec<_>>>()?;
+        if !entries.is_sorted_by(|a, b| a.hash < b.hash) {
+            return Err(object_rainbow::error_consistency!(
+                "unsorted pack index {index:?}"
+            ));
+        }
+        Ok(Self {
+            path: index.with_extension("pack"),
+            entries,
+        })
+    }
+
+    fn find(&self, hash: Hash) -> Option<Entry> {
+        let at = self
+            .entries
+            .binary_search_by_key(&hash, |entry| entry.hash)
+            .ok()?;
+        Some(self.entries[at])
+    }
+
+    fn read(&self, entry: Entry) -> std::io::Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = vec![0; entry.len.try_into().map_err(std::io::Error::other)?];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+fn load_all(dir: &Path) -> object_rainbow::Result<Arc<[Pack]>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Arc::new([])),
+        Err(e) => return Err(e.into()),
+    };
+    let mut packs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "idx") {
+            packs.push(Pack::load(&path)?);
+        }
+    }
+    Ok(packs.into())
+}
+
+fn read_from(packs: &[Pack], hash: Hash) -> Option<std::io::Result<Vec<u8>>> {
+    packs
+        .iter()
+        .find_map(|pack| Some(pack.read(pack.find(hash)?)))
+}
+
+/// Pack indices as last seen on disk. Reloaded whenever an object isn't found, in case another
+/// store has repacked since.
+#[derive(Debug, Default)]
+pub(crate) struct Packs(Mutex<Arc<[Pack]>>);
+
+impl Packs {
+    fn current(&self) -> Arc<[Pack]> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn reload(&self, dir: &Path) -> object_rainbow::Result<Arc<[Pack]>> {
+        let packs = load_all(dir)?;
+        *self.0.lock().unwrap() = packs.clone();
+        Ok(packs)
+    }
+
+    pub(crate) fn cached(&self, hash: Hash) -> bool {
+        self.current().iter().any(|pack| pack.find(hash).is_some())
+    }
+
+    pub(crate) fn contains(&self, dir: &Path, hash: Hash) -> object_rainbow::Result<bool> {
+        Ok(self.cached(hash)
+            || self
+                .reload(dir)?
+                .iter()
+                .any(|pack| pack.find(hash).is_some()))
+    }
+
+    pub(crate) fn read(&self, dir: &Path, hash: Hash) -> object_rainbow::Result<Option<Vec<u8>>> {
+        match read_from(&self.current(), hash) {
+            Some(Ok(data)) => return Ok(Some(data)),
+            Some(Err(e)) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Ok(read_from(&self.reload(dir)?, hash).transpose()?)
+    }
+}
+
+fn loose_objects(dir: &Path) -> object_rainbow::Result<Vec<(Hash, PathBuf)>> {
+    let mut objects = Vec::new();
+    let mut stack = vec![(dir.to_path_buf(), 0)];
+    while let Some((dir, depth)) = stack.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if depth < 2 {
+                stack.push((path, depth + 1));
+            } else if let Some(hash) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| Hash::from_hex(name).ok())
+            {
+                objects.push((hash, path));
+            }
+        }
+    }
+    Ok(objects)
+}
+
+fn remove_packs<'a>(packs: impl IntoIterator<Item = &'a Pack>) -> std::io::Result<()> {
+    for pack in packs {
+        std::fs::remove_file(pack.path.with_extension("idx"))?;
+        std::fs::remove_file(&pack.path)?;
+    }
+    Ok(())
+}
+
+/// Remove a loose object and the shard directories it leaves empty.
+fn remove_loose(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for dir in path.ancestors().skip(1).take(2) {
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(())
+}
+
+enum Source<'a> {
+    Packed(&'a Pack, Entry),
+    Loose(&'a Path),
+}
+
+impl Source<'_> {
+    fn read(&self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Packed(pack, entry) => pack.read(*entry),
+            Self::Loose(path) => std::fs::read(path),
+        }
+    }
+}
+
+/// `packs/.lock`, held while packs and loose objects are being rewritten, so that only one process
+/// repacks or deletes at a time. Removed on drop.
+struct PackLock(PathBuf);
+
+impl PackLock {
+    fn take(dir: &Path) -> object_rainbow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(".lock");
+        create(&path).map_err(|e| {
+            if e.kind() == ErrorKind::AlreadyExists {
+                object_rainbow::error_consistency!("packs are locked")
+            } else {
+                e.into()
+            }
+        })?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for PackLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+impl FsStore {
+    fn packs_dir(&self) -> PathBuf {
+        self.root.join("packs")
+    }
+
+    /// Write all of `sources` into a single new pack, returning its path.
+    fn write_pack(
+        &self,
+        dir: &Path,
+        sources: &BTreeMap<Hash, Source>,
+    ) -> object_rainbow::Result<PathBuf> {
+        let id = sources.keys().copied().collect::<Vec<_>>().data_hash();
+        let path = dir.join(format!("pack-{}.pack", hex::encode(id)));
+        std::fs::create_dir_all(dir)?;
+        let temp = temp_path(&path);
+        let file = create(&temp)?;
+        let mut index = Vec::with_capacity(sources.len() * ENTRY_SIZE);
+        replace(&path, &temp, file, self.sync, |file| {
+            let mut writer = BufWriter::new(file);
+            let mut offset = 0;
+            for (hash, source) in sources {
+                let data = source.read()?;
+                if data.as_slice().data_hash() != *hash {
+                    return Err(object_rainbow::error_consistency!(
+                        "hash mismatch for {}",
+                        hex::encode(hash),
+                    ));
+                }
+                writer.write_all(&data)?;
+                let len = data.len() as u64;
+                index.extend((*hash, Le(offset), Le(len)).vec());
+                offset += len;
+            }
+            writer.flush()?;
+            Ok(())
+        })?;
+        write_atomic(&path.with_extension("idx"), &index, self.sync)?;
+        Ok(path)
+    }
+
+    fn repack_blocking(&self) -> object_rainbow::Result<()> {
+        let dir = self.packs_dir();
+        let _lock = PackLock::take(&dir)?;
+        let packs = load_all(&dir)?;
+        let loose = loose_objects(&self.root.join("objects"))?;
+        if loose.is_empty() && packs.len() <= 1 {
+            return Ok(());
+        }
+        let mut sources = BTreeMap::new();
+        for pack in packs.iter() {
+            for entry in &pack.entries {
+                sources.insert(entry.hash, Source::Packed(pack, *entry));
+            }
+        }
+        for (hash, path) in &loose {
+            sources.entry(*hash).or_insert(Source::Loose(path));
+        }
+        let path = self.write_pack(&dir, &sources)?;
+        remove_packs(packs.iter().filter(|pack| pack.path != path))?;
+        for (_, path) in &loose {
+            remove_loose(path)?;
+        }
+        self.packs.reload(&dir)?;
+        Ok(())
+    }
+
+    /// Rewrite packs holding any of `hashes` without them.
+    fn delete_packed(&self, dir: &Path, hashes: &HashSet<Hash>) -> object_rainbow::Result<()> {
+        let packs = load_all(dir)?;
+        let affected = packs
+            .iter()
+            .filter(|pack| {
+                pack.entries
+                    .iter()
+                    .any(|entry| hashes.contains(&entry.hash))
+            })
+            .collect::<Vec<_>>();
+        if affected.is_empty() {
+            return Ok(());
+        }
+        let sources = affected
+            .iter()
+            .flat_map(|pack| {
+                pack.entries
+                    .iter()
+                    .filter(|entry| !hashes.contains(&entry.hash))
+                    .map(|entry| (entry.hash, Source::Packed(pack, *entry)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let path = if sources.is_empty() {
+            None
+        } else {
+            Some(self.write_pack(dir, &sources)?)
+        };
+        remove_packs(
+            affected
+                .into_iter()
+                .filter(|pack| Some(&pack.path) != path.as_ref()),
+        )?;
+        self.packs.reload(dir)?;
+        Ok(())
+    }
+
+    pub(crate) fn delete_blocking(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        let dir = self.packs_dir();
+        let _lock = PackLock::take(&dir)?;
+        for hash in hashes {
+            remove_loose(&self.object_path(*hash))?;
+            remove_loose(&self.raw_path(*hash))?;
+        }
+        self.delete_packed(&dir, &hashes.iter().copied().collect())
+    }
+
+    /// Loose and packed objects, with modification times of their files.
+    pub(crate) fn objects_blocking(
+        &self,
+    ) -> object_rainbow::Result<Vec<(Hash, Option<SystemTime>)>> {
+        let mut objects = BTreeMap::new();
+        for pack in load_all(&self.packs_dir())?.iter() {
+            let modified = std::fs::metadata(&pack.path)?.modified().ok();
+            for entry in &pack.entries {
+                objects.insert(entry.hash, modified);
+            }
+        }
+        let loose = loose_objects(&self.root.join("objects"))?
+            .into_iter()
+            .chain(loose_objects(&self.root.join("raw"))?);
+        for (hash, path) in loose {
+            let modified = match std::fs::metadata(path) {
+                Ok(metadata) => metadata.modified().ok(),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            objects.insert(hash, modified);
+        }
+        Ok(objects.into_iter().collect())
+    }
+
+    /// Move all loose objects, and objects from all existing packs, into a single new pack.
+    /// Objects under `raw/` stay where they are.
+    ///
+    /// Fails if another repack or [`RainbowStore::delete`] holds `packs/.lock`.
+    ///
+    /// [`RainbowStore::delete`]: object_rainbow_store::RainbowStore::delete
+    pub async fn repack(&self) -> object_rainbow::Result<()> {
+        let store = self.clone();
+        blocking::unblock(move || store.repack_blocking()).await
+    }
+
+    pub(crate) fn packed_contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        self.packs.contains(&self.packs_dir(), hash)
+    }
+
+    pub(crate) fn packed_read(&self, hash: Hash) -> object_rainbow::Result<Option<Vec<u8>>> {
+        self.packs.read(&self.packs_dir(), hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use futures_util::TryStreamExt;
+    use macro_rules_attribute::apply;
+    use object_rainbow::{Fetch, Singular};
+    use object_rainbow_point::{IntoPoint, Point};
+    use object_rainbow_store::RainbowStore;
+    use smol_macros::test;
+
+    use crate::FsStore;
+
+    fn count(path: PathBuf) -> usize {
+        std::fs::read_dir(path).map_or(0, |entries| entries.count())
+    }
+
+    #[apply(test!)]
+    async fn repack() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("repack");
+        let store = FsStore::new(&root);
+        let points = (0..100u8)
+            .map(|i| ([i; 5].point(), [i; 3]).point())
+            .collect::<Vec<_>>();
+        for point in &points[..50] {
+            store.save_point(point).await?;
+        }
+        assert!(count(root.join("objects")) > 0);
+        store.repack().await?;
+        assert_eq!(count(root.join("objects")), 0);
+        assert_eq!(count(root.join("packs")), 2);
+        for point in &points[50..] {
+            store.save_point(point).await?;
+        }
+        let other = FsStore::new(&root);
+        assert!(other.contains(points[0].hash()).await?);
+        store.repack().await?;
+        assert_eq!(count(root.join("objects")), 0);
+        assert_eq!(count(root.join("packs")), 2);
+        for (i, point) in points.iter().enumerate() {
+            let i = i as u8;
+            assert!(store.contains(point.hash()).await?);
+            for store in [&store, &other] {
+                let (a, b) = store
+                    .point::<(Point<[u8; 5]>, [u8; 3])>(point.hash())
+                    .fetch()
+                    .await?;
+                assert_eq!(a.fetch().await?, [i; 5]);
+                assert_eq!(b, [i; 3]);
+            }
+        }
+        store.repack().await?;
+        assert_eq!(count(root.join("packs")), 2);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn delete() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("delete");
+        let store = FsStore::new(&root);
+        let points = (0..4u8).map(|i| [i; 5].point()).collect::<Vec<_>>();
+        for point in &points[..3] {
+            store.save_point(point).await?;
+        }
+        store.repack().await?;
+        store.save_point(&points[3]).await?;
+        assert_eq!(store.list_objects().try_collect::<Vec<_>>().await?.len(), 4);
+        store.delete(&[points[0].hash(), points[3].hash()]).await?;
+        assert_eq!(count(root.join("objects")), 0);
+        assert_eq!(count(root.join("packs")), 2);
+        for (point, contains) in points.iter().zip([false, true, true, false]) {
+            assert_eq!(store.contains(point.hash()).await?, contains);
+        }
+        assert_eq!(
+            store.point::<[u8; 5]>(points[1].hash()).fetch().await?,
+            [1; 5]
+        );
+        store.delete(&[points[1].hash(), points[2].hash()]).await?;
+        assert_eq!(count(root.join("packs")), 0);
+        assert!(
+            store
+                .list_objects()
+                .try_collect::<Vec<_>>()
+                .await?
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn locked() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("locked");
+        let store = FsStore::new(&root);
+        let point = [0u8; 5].point();
+        store.save_point(&point).await?;
+        std::fs::create_dir_all(root.join("packs"))?;
+        std::fs::write(root.join("packs/.lock"), b"")?;
+        assert!(store.repack().await.is_err());
+        assert!(store.delete(&[point.hash()]).await.is_err());
+        assert!(store.contains(point.hash()).await?);
+        std::fs::remove_file(root.join("packs/.lock"))?;
+        store.repack().await?;
+        assert!(!root.join("packs/.lock").exists());
+        store.delete(&[point.hash()]).await?;
+        assert!(!store.contains(point.hash()).await?);
+        Ok(())
+    }
+}
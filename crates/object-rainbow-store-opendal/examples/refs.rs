@@ -0,0 +1,75 @@
+use futures_util::TryStreamExt;
+use object_rainbow::{FullHash, OptionalHash};
+use object_rainbow_store::{RainbowStore, RainbowStoreMut, ReflogStore};
+use object_rainbow_store_opendal::OpendalStore;
+use opendal::{Operator, services::Memory};
+
+async fn refs(store: &OpendalStore) -> anyhow::Result<()> {
+    let a = b"a".full_hash();
+    let b = b"b".full_hash();
+    store.update_ref("heads/main", None, a).await?;
+    store.update_ref("heads/dev", None, b).await?;
+    store.update_ref("tags/v1", None, a).await?;
+    let mut refs = store.list_refs("heads/").try_collect::<Vec<_>>().await?;
+    refs.sort();
+    assert_eq!(refs, [("heads/dev".into(), b), ("heads/main".into(), a)]);
+    assert_eq!(store.list_refs("").try_collect::<Vec<_>>().await?.len(), 3);
+    assert!(store.delete_ref("tags/v1", Some(b.into())).await.is_err());
+    store.delete_ref("tags/v1", Some(a.into())).await?;
+    store.delete_ref("tags/v1", None).await?;
+    assert!(!store.ref_exists("tags/v1").await?);
+    store.rename_ref("heads/dev", "heads/feature").await?;
+    assert_eq!(store.fetch_ref("heads/dev").await?, OptionalHash::NONE);
+    assert_eq!(store.fetch_ref("heads/feature").await?, b);
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    smol::block_on(async move {
+        let store = OpendalStore::from_operator(Operator::new(Memory::default())?.finish());
+        refs(&store).await?;
+        let data = b"data".full_hash();
+        store.save_raw(data, b"data").await?;
+        // an adapter keeping its own refs and logs under `refs/` and `logs/` in the flat layout
+        let reflog = ReflogStore::new(store.clone());
+        reflog.update_ref("main", None, data).await?;
+        reflog.update_ref("main", None, b"a".full_hash()).await?;
+        let store = store.migrate_to_prefixes().await?;
+        assert!(store.contains(data).await?);
+        assert_eq!(store.fetch_ref("heads/main").await?, b"a".full_hash());
+        let reflog = ReflogStore::new(store.clone());
+        assert_eq!(reflog.fetch_ref("main").await?, b"a".full_hash());
+        assert_eq!(reflog.reflog("main").await?.len(), 2);
+        let listed = store.list_refs("").try_collect::<Vec<_>>().await?;
+        let store = store.migrate_to_prefixes().await?;
+        assert_eq!(store.list_refs("").try_collect::<Vec<_>>().await?, listed);
+        assert_eq!(reflog.fetch_ref("main").await?, b"a".full_hash());
+        assert_eq!(
+            store
+                .list_refs("heads/")
+                .try_collect::<Vec<_>>()
+                .await?
+                .len(),
+            2
+        );
+        let store =
+            OpendalStore::from_operator(Operator::new(Memory::default())?.finish()).with_prefixes();
+        refs(&store).await?;
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        let hex = hex::encode(a);
+        store.update_ref(&hex, None, b).await?;
+        assert_eq!(
+            store.list_refs(&hex).try_collect::<Vec<_>>().await?,
+            [(hex, b)]
+        );
+        assert!(
+            store
+                .list_objects()
+                .try_collect::<Vec<_>>()
+                .await?
+                .is_empty()
+        );
+        Ok(())
+    })
+}
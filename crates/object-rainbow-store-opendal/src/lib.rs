@@ -1,12 +1,22 @@
-use std::sync::Arc;
+use std::{future::IntoFuture, sync::Arc, time::SystemTime};
 
+use futures_util::{Stream, TryStreamExt};
 use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash};
-use object_rainbow_store::{RainbowStore, RainbowStoreMut};
-use opendal::{ErrorKind, Operator};
+use object_rainbow_store::{RainbowStore, RainbowStoreMut, RefUpdate};
+use opendal::{EntryMode, ErrorKind, Operator};
 
+/// [`RainbowStore`] on top of any [opendal](opendal) service.
+///
+/// By default, objects are kept under `<hash>` and refs under `<key>`, side by side, so refs whose
+/// keys look like object hashes can't be listed, and other files look like refs. Stores made
+/// [`OpendalStore::with_prefixes`] keep objects under `objects/<hash>` and refs under
+/// `refs/<key>` instead. The two layouts can't read each other's data, see
+/// [`OpendalStore::migrate_to_prefixes`].
 #[derive(Debug, Clone)]
 pub struct OpendalStore {
     operator: Operator,
+    concurrency: usize,
+    prefixed: bool,
     ptr: Arc<()>,
 }
 
@@ -14,9 +24,122 @@ impl OpendalStore {
     pub fn from_operator(operator: Operator) -> Self {
         Self {
             operator,
+            concurrency: 16,
+            prefixed: false,
             ptr: Default::default(),
         }
     }
+
+    /// Keep at most `concurrency` requests in flight when saving a tree.
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency,
+            ..self
+        }
+    }
+
+    /// Keep objects under `objects/` and refs under `refs/`.
+    pub fn with_prefixes(self) -> Self {
+        Self {
+            prefixed: true,
+            ..self
+        }
+    }
+
+    /// Move everything stored in the default layout to where [`Self::with_prefixes`] expects it,
+    /// and return the store in that layout. Nothing else may use the store meanwhile.
+    ///
+    /// Files are moved as listed at the start, recorded in `.migration`, along with how many of
+    /// them are done in `.migration-progress`, so an interrupted migration can be run again, and
+    /// running it once it's done changes nothing. Refs of the default layout can't have those
+    /// keys.
+    pub async fn migrate_to_prefixes(self) -> object_rainbow::Result<Self> {
+        let pending = match self.operator.read(MIGRATION).await {
+            Ok(pending) => String::from_utf8(pending.to_vec())
+                .map_err(object_rainbow::Error::parse)?
+                .lines()
+                .map(|path| {
+                    String::from_utf8(hex::decode(path).map_err(object_rainbow::Error::parse)?)
+                        .map_err(object_rainbow::Error::parse)
+                })
+                .collect::<object_rainbow::Result<Vec<_>>>()?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let mut pending = self
+                    .operator
+                    .list_with("")
+                    .recursive(true)
+                    .await
+                    .map_err(object_rainbow::Error::io)?
+                    .into_iter()
+                    .filter(|entry| entry.metadata().mode() == EntryMode::FILE)
+                    .map(|entry| entry.path().to_owned())
+                    .collect::<Vec<_>>();
+                // each file moves to a longer path, which only another file moved earlier can have
+                pending.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+                let listed = pending
+                    .iter()
+                    .map(|path| hex::encode(path) + "\n")
+                    .collect::<String>();
+                self.operator
+                    .write(MIGRATION, listed)
+                    .await
+                    .map_err(object_rainbow::Error::io)?;
+                pending
+            }
+            Err(e) => return Err(object_rainbow::Error::io(e)),
+        };
+        let done = match self.operator.read(MIGRATION_PROGRESS).await {
+            Ok(done) => String::from_utf8(done.to_vec())
+                .map_err(object_rainbow::Error::parse)?
+                .parse()
+                .map_err(object_rainbow::Error::parse)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(object_rainbow::Error::io(e)),
+        };
+        for (i, path) in pending.iter().enumerate().skip(done) {
+            // already moved if the previous run stopped before recording it
+            match self.operator.read(path).await {
+                Ok(data) => {
+                    let to = if is_object_key(path) {
+                        format!("{OBJECTS}{path}")
+                    } else {
+                        format!("{REFS}{path}")
+                    };
+                    self.operator
+                        .write(&to, data)
+                        .await
+                        .map_err(object_rainbow::Error::io)?;
+                    self.operator
+                        .delete(path)
+                        .await
+                        .map_err(object_rainbow::Error::io)?;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(object_rainbow::Error::io(e)),
+            }
+            self.operator
+                .write(MIGRATION_PROGRESS, (i + 1).to_string())
+                .await
+                .map_err(object_rainbow::Error::io)?;
+        }
+        Ok(self.with_prefixes())
+    }
+
+    fn objects(&self) -> &'static str {
+        if self.prefixed { OBJECTS } else { "" }
+    }
+
+    fn refs(&self) -> &'static str {
+        if self.prefixed { REFS } else { "" }
+    }
+
+    fn to_key(&self, hash: Hash) -> String {
+        format!("{}{}", self.objects(), hex::encode(hash))
+    }
+
+    fn ref_key(&self, key: &str) -> String {
+        format!("{}{key}", self.refs())
+    }
 }
 
 impl PartialEq for OpendalStore {
@@ -25,8 +148,13 @@ impl PartialEq for OpendalStore {
     }
 }
 
-fn to_key(hash: Hash) -> String {
-    hex::encode(hash)
+const OBJECTS: &str = "objects/";
+const REFS: &str = "refs/";
+const MIGRATION: &str = ".migration";
+const MIGRATION_PROGRESS: &str = ".migration-progress";
+
+fn is_object_key(key: &str) -> bool {
+    key.len() == 64 && key.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
 }
 
 impl RainbowStore for OpendalStore {
@@ -35,15 +163,27 @@ impl RainbowStore for OpendalStore {
         wh: WithHash<'_, impl Send + Sync + ToOutput>,
     ) -> object_rainbow::Result<()> {
         self.operator
-            .write(&to_key(wh.data_hash()), wh.data.vec())
+            .write(&self.to_key(wh.data_hash()), wh.data.vec())
             .await
             .map_err(object_rainbow::Error::io)?;
         Ok(())
     }
 
+    async fn save_raw(&self, hash: Hash, data: &[u8]) -> object_rainbow::Result<()> {
+        self.operator
+            .write(&self.to_key(hash), data.to_vec())
+            .await
+            .map_err(object_rainbow::Error::io)?;
+        Ok(())
+    }
+
+    fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
     async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
         self.operator
-            .exists(&to_key(hash))
+            .exists(&self.to_key(hash))
             .await
             .map_err(object_rainbow::Error::io)
     }
@@ -53,11 +193,42 @@ impl RainbowStore for OpendalStore {
         hash: Hash,
     ) -> object_rainbow::Result<impl 'static + Send + Sync + AsRef<[u8]>> {
         self.operator
-            .read(&to_key(hash))
+            .read(&self.to_key(hash))
             .await
             .map_err(object_rainbow::Error::io)
             .map(|b| b.to_bytes())
     }
+
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        futures_util::stream::once(self.operator.lister(self.objects()).into_future())
+            .try_flatten()
+            .map_err(object_rainbow::Error::io)
+            .try_filter_map(async |entry| {
+                let Some(key) = entry.path().strip_prefix(self.objects()) else {
+                    return Ok(None);
+                };
+                if entry.metadata().mode() != EntryMode::FILE || !is_object_key(key) {
+                    return Ok(None);
+                }
+                let hash = hex::decode(key).map_err(object_rainbow::Error::parse)?;
+                Ok(Some((
+                    Hash::parse_slice_refless(&hash)?,
+                    entry.metadata().last_modified().map(SystemTime::from),
+                )))
+            })
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        for hash in hashes {
+            self.operator
+                .delete(&self.to_key(*hash))
+                .await
+                .map_err(object_rainbow::Error::io)?;
+        }
+        Ok(())
+    }
 }
 
 impl RainbowStoreMut for OpendalStore {
@@ -68,14 +239,14 @@ impl RainbowStoreMut for OpendalStore {
         hash: Hash,
     ) -> object_rainbow::Result<()> {
         self.operator
-            .write(key, hash.to_vec())
+            .write(&self.ref_key(key), hash.to_vec())
             .await
             .map_err(object_rainbow::Error::io)?;
         Ok(())
     }
 
     async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
-        match self.operator.read(key).await {
+        match self.operator.read(&self.ref_key(key)).await {
             Ok(value) => OptionalHash::parse_slice_refless(&value.to_vec()),
             Err(e) if e.kind() == ErrorKind::NotFound => Ok(Default::default()),
             Err(e) => Err(object_rainbow::Error::io(e)),
@@ -84,7 +255,54 @@ impl RainbowStoreMut for OpendalStore {
 
     async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
         self.operator
-            .exists(key)
+            .exists(&self.ref_key(key))
+            .await
+            .map_err(object_rainbow::Error::io)
+    }
+
+    /// Without [`OpendalStore::with_prefixes`], keys that look like object hashes are skipped.
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        let prefix = self.ref_key(prefix);
+        let filter = prefix.clone();
+        futures_util::stream::once(async move {
+            self.operator.lister_with(&prefix).recursive(true).await
+        })
+        .try_flatten()
+        .map_err(object_rainbow::Error::io)
+        .try_filter_map(move |entry| {
+            let prefix = filter.clone();
+            async move {
+                let path = entry.path();
+                let Some(key) = path.strip_prefix(self.refs()) else {
+                    return Ok(None);
+                };
+                if entry.metadata().mode() != EntryMode::FILE
+                    || !path.starts_with(&prefix)
+                    || (!self.prefixed && is_object_key(key))
+                {
+                    return Ok(None);
+                }
+                Ok(self
+                    .fetch_ref(key)
+                    .await?
+                    .get()
+                    .map(|hash| (key.to_owned(), hash)))
+            }
+        })
+    }
+
+    /// `old` is checked before deleting, but not atomically.
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        if let Some(old) = old
+            && self.fetch_ref(key).await? != old
+        {
+            return Err(RefUpdate::changed(key));
+        }
+        self.operator
+            .delete(&self.ref_key(key))
             .await
             .map_err(object_rainbow::Error::io)
     }
@@ -0,0 +1,437 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::{Stream, TryStreamExt};
+use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash};
+use object_rainbow_store::{RainbowStore, RainbowStoreMut, RefUpdate};
+use redb::{Database, ReadableDatabase, ReadableTable, TableDefinition, WriteTransaction};
+
+const OBJECTS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("objects");
+const REFS: TableDefinition<&str, &[u8]> = TableDefinition::new("refs");
+const WRITTEN: TableDefinition<&[u8], u64> = TableDefinition::new("written");
+
+fn db(e: impl Into<redb::Error>) -> object_rainbow::Error {
+    object_rainbow::Error::operation(e.into())
+}
+
+/// [`RainbowStore`] keeping objects and refs in two tables of a [redb](redb) database.
+///
+/// A third table keeps when each object was written, in milliseconds since the Unix epoch, for
+/// [`RainbowStore::list_objects`]. Objects saved before it existed have no time.
+///
+/// All ref updates happen in write transactions, so [`RainbowStoreMut::update_refs`] can apply several
+/// at once, and [`RainbowStoreMut::list_refs`] uses the ordering of keys.
+#[derive(Debug, Clone)]
+pub struct RedbStore {
+    database: Arc<Database>,
+}
+
+impl PartialEq for RedbStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.database, &other.database)
+    }
+}
+
+fn fetch_ref(
+    refs: &impl ReadableTable<&'static str, &'static [u8]>,
+    key: &str,
+) -> object_rainbow::Result<OptionalHash> {
+    match refs.get(key).map_err(db)? {
+        Some(hash) => OptionalHash::parse_slice_refless(hash.value()),
+        None => Ok(OptionalHash::NONE),
+    }
+}
+
+fn check_ref(
+    transaction: &WriteTransaction,
+    key: &str,
+    old: Option<OptionalHash>,
+) -> object_rainbow::Result<bool> {
+    let Some(old) = old else {
+        return Ok(true);
+    };
+    let refs = transaction.open_table(REFS).map_err(db)?;
+    Ok(fetch_ref(&refs, key)? == old)
+}
+
+impl RedbStore {
+    pub fn open(path: impl AsRef<Path>) -> object_rainbow::Result<Self> {
+        Self::from_database(Database::create(path).map_err(db)?)
+    }
+
+    /// Use an existing database, creating the tables if they're missing.
+    pub fn from_database(database: Database) -> object_rainbow::Result<Self> {
+        let transaction = database.begin_write().map_err(db)?;
+        transaction.open_table(OBJECTS).map_err(db)?;
+        transaction.open_table(REFS).map_err(db)?;
+        transaction.open_table(WRITTEN).map_err(db)?;
+        transaction.commit().map_err(db)?;
+        Ok(Self {
+            database: Arc::new(database),
+        })
+    }
+
+    async fn with<T: 'static + Send>(
+        &self,
+        f: impl 'static + Send + FnOnce(&Database) -> object_rainbow::Result<T>,
+    ) -> object_rainbow::Result<T> {
+        let database = self.database.clone();
+        blocking::unblock(move || f(&database)).await
+    }
+
+    async fn insert(&self, objects: Vec<(Hash, Vec<u8>)>) -> object_rainbow::Result<()> {
+        self.with(move |database| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64);
+            let transaction = database.begin_write().map_err(db)?;
+            {
+                let mut table = transaction.open_table(OBJECTS).map_err(db)?;
+                let mut written = transaction.open_table(WRITTEN).map_err(db)?;
+                for (hash, data) in &objects {
+                    if table.get(hash.as_slice()).map_err(db)?.is_none() {
+                        table.insert(hash.as_slice(), data.as_slice()).map_err(db)?;
+                        written.insert(hash.as_slice(), now).map_err(db)?;
+                    }
+                }
+            }
+            transaction.commit().map_err(db)
+        })
+        .await
+    }
+}
+
+impl RainbowStore for RedbStore {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        self.insert(vec![(wh.data_hash(), wh.data.vec())]).await
+    }
+
+    async fn save_raw(&self, hash: Hash, data: &[u8]) -> object_rainbow::Result<()> {
+        self.insert(vec![(hash, data.to_vec())]).await
+    }
+
+    /// Check several hashes within one transaction.
+    async fn contains_many(&self, hashes: &[Hash]) -> object_rainbow::Result<Vec<bool>> {
+        let hashes = hashes.to_vec();
+        self.with(move |database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let objects = transaction.open_table(OBJECTS).map_err(db)?;
+            hashes
+                .iter()
+                .map(|hash| Ok(objects.get(hash.as_slice()).map_err(db)?.is_some()))
+                .collect()
+        })
+        .await
+    }
+
+    /// Save several objects within one transaction.
+    async fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> object_rainbow::Result<()> {
+        self.insert(
+            objects
+                .into_iter()
+                .map(|wh| (wh.data_hash(), wh.data.vec()))
+                .collect(),
+        )
+        .await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        self.with(move |database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let objects = transaction.open_table(OBJECTS).map_err(db)?;
+            Ok(objects.get(hash.as_slice()).map_err(db)?.is_some())
+        })
+        .await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Vec<u8>> {
+        self.with(move |database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let objects = transaction.open_table(OBJECTS).map_err(db)?;
+            objects
+                .get(hash.as_slice())
+                .map_err(db)?
+                .map(|data| data.value().to_vec())
+                .ok_or(object_rainbow::Error::HashNotFound)
+        })
+        .await
+    }
+
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        futures_util::stream::once(self.with(|database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let objects = transaction.open_table(OBJECTS).map_err(db)?;
+            let written = transaction.open_table(WRITTEN).map_err(db)?;
+            let mut hashes = Vec::new();
+            for entry in objects.iter().map_err(db)? {
+                let (hash, _) = entry.map_err(db)?;
+                let written = written
+                    .get(hash.value())
+                    .map_err(db)?
+                    .map(|written| UNIX_EPOCH + Duration::from_millis(written.value()));
+                hashes.push((Hash::parse_slice_refless(hash.value())?, written));
+            }
+            Ok(hashes)
+        }))
+        .map_ok(|hashes| futures_util::stream::iter(hashes.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        let hashes = hashes.to_vec();
+        self.with(move |database| {
+            let transaction = database.begin_write().map_err(db)?;
+            {
+                let mut objects = transaction.open_table(OBJECTS).map_err(db)?;
+                let mut written = transaction.open_table(WRITTEN).map_err(db)?;
+                for hash in &hashes {
+                    objects.remove(hash.as_slice()).map_err(db)?;
+                    written.remove(hash.as_slice()).map_err(db)?;
+                }
+            }
+            transaction.commit().map_err(db)
+        })
+        .await
+    }
+}
+
+impl RainbowStoreMut for RedbStore {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.update_refs(vec![RefUpdate {
+            key: key.to_owned(),
+            old,
+            hash,
+        }])
+        .await
+    }
+
+    /// Applies all updates in one write transaction.
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        self.with(move |database| {
+            let transaction = database.begin_write().map_err(db)?;
+            let mut conflicts = Vec::new();
+            for RefUpdate { key, old, .. } in &updates {
+                if !check_ref(&transaction, key, *old)? {
+                    conflicts.push(key);
+                }
+            }
+            if !conflicts.is_empty() {
+                return Err(RefUpdate::conflict_error(&conflicts));
+            }
+            {
+                let mut refs = transaction.open_table(REFS).map_err(db)?;
+                for RefUpdate { key, hash, .. } in &updates {
+                    refs.insert(key.as_str(), hash.as_slice()).map_err(db)?;
+                }
+            }
+            transaction.commit().map_err(db)
+        })
+        .await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        let key = key.to_owned();
+        self.with(move |database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let refs = transaction.open_table(REFS).map_err(db)?;
+            fetch_ref(&refs, &key)
+        })
+        .await
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        let key = key.to_owned();
+        self.with(move |database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let refs = transaction.open_table(REFS).map_err(db)?;
+            Ok(refs.get(key.as_str()).map_err(db)?.is_some())
+        })
+        .await
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        let prefix = prefix.to_owned();
+        futures_util::stream::once(self.with(move |database| {
+            let transaction = database.begin_read().map_err(db)?;
+            let refs = transaction.open_table(REFS).map_err(db)?;
+            let mut list = Vec::new();
+            for entry in refs.range(prefix.as_str()..).map_err(db)? {
+                let (key, hash) = entry.map_err(db)?;
+                if !key.value().starts_with(&prefix) {
+                    break;
+                }
+                if let Some(hash) = OptionalHash::parse_slice_refless(hash.value())?.get() {
+                    list.push((key.value().to_owned(), hash));
+                }
+            }
+            Ok(list)
+        }))
+        .map_ok(|refs| futures_util::stream::iter(refs.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        let key = key.to_owned();
+        self.with(move |database| {
+            let transaction = database.begin_write().map_err(db)?;
+            if !check_ref(&transaction, &key, old)? {
+                return Err(RefUpdate::changed(&key));
+            }
+            transaction
+                .open_table(REFS)
+                .map_err(db)?
+                .remove(key.as_str())
+                .map_err(db)?;
+            transaction.commit().map_err(db)
+        })
+        .await
+    }
+
+    /// Done in one transaction.
+    async fn rename_ref(&self, from: &str, to: &str) -> object_rainbow::Result<()> {
+        let from = from.to_owned();
+        let to = to.to_owned();
+        self.with(move |database| {
+            let transaction = database.begin_write().map_err(db)?;
+            {
+                let mut refs = transaction.open_table(REFS).map_err(db)?;
+                let hash = fetch_ref(&refs, &from)?
+                    .get()
+                    .ok_or(object_rainbow::Error::HashNotFound)?;
+                if fetch_ref(&refs, &to)?.get().is_some() {
+                    return Err(RefUpdate::changed(&to));
+                }
+                refs.remove(from.as_str()).map_err(db)?;
+                refs.insert(to.as_str(), hash.as_slice()).map_err(db)?;
+            }
+            transaction.commit().map_err(db)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use futures_util::TryStreamExt;
+    use macro_rules_attribute::apply;
+    use object_rainbow::{Fetch, FullHash, OptionalHash, Singular};
+    use object_rainbow_point::{IntoPoint, Point};
+    use object_rainbow_store::{RainbowStore, RainbowStoreMut, RefTransaction, StoreMut};
+    use smol_macros::test;
+
+    use crate::RedbStore;
+
+    #[apply(test!)]
+    async fn objects() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("objects");
+        let point = ((*b"abc").point(), *b"def").point();
+        {
+            let store = RedbStore::open(&path)?;
+            store.save_point(&point).await?;
+            assert!(store.contains(point.hash()).await?);
+        }
+        let store = RedbStore::open(&path)?;
+        let (abc, def) = store
+            .point::<(Point<[u8; 3]>, [u8; 3])>(point.hash())
+            .fetch()
+            .await?;
+        assert_eq!(abc.fetch().await?, *b"abc");
+        assert_eq!(def, *b"def");
+        assert!(!store.contains(b"x".full_hash()).await?);
+        let listed = store.list_objects().try_collect::<Vec<_>>().await?;
+        assert_eq!(listed.len(), 2);
+        assert!(listed.iter().all(|(_, written)| written.is_some()));
+        store.delete(&[abc.hash(), b"x".full_hash()]).await?;
+        assert!(!store.contains(abc.hash()).await?);
+        let listed = store.list_objects().try_collect::<Vec<_>>().await?;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].0, point.hash());
+        store.save_raw(abc.hash(), b"abc").await?;
+        assert_eq!(store.point::<[u8; 3]>(abc.hash()).fetch().await?, *b"abc");
+        drop(store);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn refs() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("refs");
+        let store = RedbStore::open(&path)?;
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        store
+            .update_ref("heads/main", Some(OptionalHash::NONE), a)
+            .await?;
+        assert!(
+            store
+                .update_ref("heads/main", Some(OptionalHash::NONE), b)
+                .await
+                .is_err()
+        );
+        store.update_ref("heads/main", Some(a.into()), b).await?;
+        assert_eq!(store.fetch_ref("heads/main").await?, b);
+        let mut transaction = RefTransaction::new(store.clone());
+        transaction
+            .update("heads/dev", Some(OptionalHash::NONE), a)
+            .update("heads/main", Some(b.into()), a)
+            .update("tags/v1", None, b);
+        transaction.commit().await?;
+        let mut transaction = RefTransaction::new(store.clone());
+        transaction
+            .update("heads/dev", Some(a.into()), b)
+            .update("heads/main", Some(b.into()), b);
+        let error = transaction.commit().await.unwrap_err().to_string();
+        assert!(error.contains("heads/main") && !error.contains("heads/dev"));
+        assert_eq!(store.fetch_ref("heads/dev").await?, a);
+        assert_eq!(
+            store.list_refs("heads/").try_collect::<Vec<_>>().await?,
+            [("heads/dev".into(), a), ("heads/main".into(), a)],
+        );
+        assert_eq!(store.list_refs("").try_collect::<Vec<_>>().await?.len(), 3);
+        assert!(
+            store
+                .list_refs("x")
+                .try_collect::<Vec<_>>()
+                .await?
+                .is_empty()
+        );
+        assert!(store.delete_ref("tags/v1", Some(a.into())).await.is_err());
+        store.delete_ref("tags/v1", Some(b.into())).await?;
+        assert!(!store.ref_exists("tags/v1").await?);
+        assert!(store.rename_ref("heads/dev", "heads/main").await.is_err());
+        store.rename_ref("heads/dev", "heads/feature").await?;
+        assert_eq!(store.fetch_ref("heads/dev").await?, OptionalHash::NONE);
+        assert_eq!(store.fetch_ref("heads/feature").await?, a);
+        let stored = StoreMut::new(store.clone())
+            .init("test", (*b"c").point())
+            .await?;
+        let loaded = StoreMut::new(store.clone())
+            .load::<[u8; 1], _>("test")
+            .await?;
+        assert_eq!(loaded.hash(), stored.hash());
+        drop(store);
+        Ok(())
+    }
+}
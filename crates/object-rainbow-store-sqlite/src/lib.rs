@@ -0,0 +1,544 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::{Stream, TryStreamExt};
+use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash};
+use object_rainbow_store::{RainbowStore, RainbowStoreMut, RefUpdate};
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+
+fn sql(e: rusqlite::Error) -> object_rainbow::Error {
+    object_rainbow::Error::operation(e)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+/// [`RainbowStore`] keeping objects in a `hash → data` table and refs in a `key → hash` table of
+/// a SQLite database.
+///
+/// Objects also get a `written` column, in milliseconds since the Unix epoch, for
+/// [`RainbowStore::list_objects`]. Tables created before that have it added, with no time for the
+/// objects already there.
+///
+/// Ref updates with an expected old value are checked and applied within one transaction, which
+/// also makes [`RainbowStoreMut::update_refs`] atomic.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl PartialEq for SqliteStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.connection, &other.connection)
+    }
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> object_rainbow::Result<Self> {
+        Self::from_connection(Connection::open(path).map_err(sql)?)
+    }
+
+    /// Use an existing connection, creating the tables if they're missing.
+    pub fn from_connection(connection: Connection) -> object_rainbow::Result<Self> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS objects (
+                    hash BLOB PRIMARY KEY,
+                    data BLOB NOT NULL,
+                    written INTEGER
+                ) WITHOUT ROWID;
+                CREATE TABLE IF NOT EXISTS refs (
+                    key TEXT PRIMARY KEY,
+                    hash BLOB NOT NULL
+                ) WITHOUT ROWID;",
+            )
+            .map_err(sql)?;
+        let written = connection
+            .prepare("SELECT 1 FROM pragma_table_info('objects') WHERE name = 'written'")
+            .and_then(|mut statement| statement.exists([]))
+            .map_err(sql)?;
+        if !written {
+            connection
+                .execute("ALTER TABLE objects ADD COLUMN written INTEGER", [])
+                .map_err(sql)?;
+        }
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn with<T: 'static + Send>(
+        &self,
+        f: impl 'static + Send + FnOnce(&mut Connection) -> object_rainbow::Result<T>,
+    ) -> object_rainbow::Result<T> {
+        let connection = self.connection.clone();
+        blocking::unblock(move || f(&mut connection.lock().unwrap())).await
+    }
+
+    async fn insert(&self, objects: Vec<(Hash, Vec<u8>)>) -> object_rainbow::Result<()> {
+        self.with(move |connection| {
+            let transaction = connection.transaction().map_err(sql)?;
+            {
+                let mut statement = transaction
+                    .prepare_cached(
+                        "INSERT OR IGNORE INTO objects (hash, data, written) VALUES (?1, ?2, ?3)",
+                    )
+                    .map_err(sql)?;
+                let written = now();
+                for (hash, data) in &objects {
+                    statement
+                        .execute(params![hash.as_slice(), data, written])
+                        .map_err(sql)?;
+                }
+            }
+            transaction.commit().map_err(sql)
+        })
+        .await
+    }
+}
+
+impl RainbowStore for SqliteStore {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        self.insert(vec![(wh.data_hash(), wh.data.vec())]).await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        self.with(move |connection| {
+            connection
+                .prepare_cached("SELECT 1 FROM objects WHERE hash = ?1")
+                .and_then(|mut statement| statement.exists([hash.as_slice()]))
+                .map_err(sql)
+        })
+        .await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Vec<u8>> {
+        self.with(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM objects WHERE hash = ?1",
+                    [hash.as_slice()],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(sql)?
+                .ok_or(object_rainbow::Error::HashNotFound)
+        })
+        .await
+    }
+
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        futures_util::stream::once(self.with(|connection| {
+            let mut statement = connection
+                .prepare_cached("SELECT hash, written FROM objects")
+                .map_err(sql)?;
+            let mut rows = statement.query([]).map_err(sql)?;
+            let mut hashes = Vec::new();
+            while let Some(row) = rows.next().map_err(sql)? {
+                let hash = row.get::<_, Vec<u8>>(0).map_err(sql)?;
+                let written = row.get::<_, Option<i64>>(1).map_err(sql)?.map(|written| {
+                    UNIX_EPOCH + Duration::from_millis(written.try_into().unwrap_or(0))
+                });
+                hashes.push((Hash::parse_slice_refless(&hash)?, written));
+            }
+            Ok(hashes)
+        }))
+        .map_ok(|hashes| futures_util::stream::iter(hashes.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        let hashes = hashes.to_vec();
+        self.with(move |connection| {
+            let transaction = connection.transaction().map_err(sql)?;
+            {
+                let mut statement = transaction
+                    .prepare_cached("DELETE FROM objects WHERE hash = ?1")
+                    .map_err(sql)?;
+                for hash in &hashes {
+                    statement.execute([hash.as_slice()]).map_err(sql)?;
+                }
+            }
+            transaction.commit().map_err(sql)
+        })
+        .await
+    }
+
+    async fn save_raw(&self, hash: Hash, data: &[u8]) -> object_rainbow::Result<()> {
+        self.insert(vec![(hash, data.to_vec())]).await
+    }
+
+    /// Check several hashes within one transaction.
+    async fn contains_many(&self, hashes: &[Hash]) -> object_rainbow::Result<Vec<bool>> {
+        let hashes = hashes.to_vec();
+        self.with(move |connection| {
+            let transaction = connection.transaction().map_err(sql)?;
+            let contains = {
+                let mut statement = transaction
+                    .prepare_cached("SELECT 1 FROM objects WHERE hash = ?1")
+                    .map_err(sql)?;
+                hashes
+                    .iter()
+                    .map(|hash| statement.exists([hash.as_slice()]).map_err(sql))
+                    .collect::<object_rainbow::Result<_>>()?
+            };
+            transaction.commit().map_err(sql)?;
+            Ok(contains)
+        })
+        .await
+    }
+
+    /// Save several objects within one transaction.
+    async fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> object_rainbow::Result<()> {
+        self.insert(
+            objects
+                .into_iter()
+                .map(|wh| (wh.data_hash(), wh.data.vec()))
+                .collect(),
+        )
+        .await
+    }
+}
+
+fn check_ref(connection: &Connection, key: &str, old: OptionalHash) -> object_rainbow::Result<()> {
+    if fetch_ref(connection, key)? != old {
+        return Err(RefUpdate::changed(key));
+    }
+    Ok(())
+}
+
+fn set_ref(connection: &Connection, key: &str, hash: Hash) -> object_rainbow::Result<()> {
+    connection
+        .execute(
+            "INSERT INTO refs (key, hash) VALUES (?1, ?2)
+            ON CONFLICT (key) DO UPDATE SET hash = excluded.hash",
+            params![key, hash.as_slice()],
+        )
+        .map_err(sql)?;
+    Ok(())
+}
+
+fn fetch_ref(connection: &Connection, key: &str) -> object_rainbow::Result<OptionalHash> {
+    match connection
+        .query_row("SELECT hash FROM refs WHERE key = ?1", [key], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })
+        .optional()
+        .map_err(sql)?
+    {
+        Some(hash) => OptionalHash::parse_slice_refless(&hash),
+        None => Ok(OptionalHash::NONE),
+    }
+}
+
+impl RainbowStoreMut for SqliteStore {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        let key = key.to_owned();
+        self.with(move |connection| {
+            let transaction = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(sql)?;
+            if let Some(old) = old {
+                check_ref(&transaction, &key, old)?;
+            }
+            set_ref(&transaction, &key, hash)?;
+            transaction.commit().map_err(sql)
+        })
+        .await
+    }
+
+    /// Applies all updates in one transaction.
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        self.with(move |connection| {
+            let transaction = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(sql)?;
+            let mut conflicts = Vec::new();
+            for RefUpdate { key, old, .. } in &updates {
+                if let Some(old) = *old
+                    && fetch_ref(&transaction, key)? != old
+                {
+                    conflicts.push(key);
+                }
+            }
+            if !conflicts.is_empty() {
+                return Err(RefUpdate::conflict_error(&conflicts));
+            }
+            for RefUpdate { key, hash, .. } in &updates {
+                set_ref(&transaction, key, *hash)?;
+            }
+            transaction.commit().map_err(sql)
+        })
+        .await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        let key = key.to_owned();
+        self.with(move |connection| fetch_ref(connection, &key))
+            .await
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        let key = key.to_owned();
+        self.with(move |connection| {
+            connection
+                .prepare_cached("SELECT 1 FROM refs WHERE key = ?1")
+                .and_then(|mut statement| statement.exists([key]))
+                .map_err(sql)
+        })
+        .await
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        let prefix = prefix.to_owned();
+        futures_util::stream::once(self.with(move |connection| {
+            let mut statement = connection
+                .prepare_cached(
+                    "SELECT key, hash FROM refs
+                    WHERE substr(key, 1, length(?1)) = ?1
+                    ORDER BY key",
+                )
+                .map_err(sql)?;
+            let mut rows = statement.query([prefix]).map_err(sql)?;
+            let mut refs = Vec::new();
+            while let Some(row) = rows.next().map_err(sql)? {
+                let hash = row.get::<_, Vec<u8>>(1).map_err(sql)?;
+                if let Some(hash) = OptionalHash::parse_slice_refless(&hash)?.get() {
+                    refs.push((row.get(0).map_err(sql)?, hash));
+                }
+            }
+            Ok(refs)
+        }))
+        .map_ok(|refs| futures_util::stream::iter(refs.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        let key = key.to_owned();
+        self.with(move |connection| {
+            let transaction = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(sql)?;
+            if let Some(old) = old {
+                check_ref(&transaction, &key, old)?;
+            }
+            transaction
+                .execute("DELETE FROM refs WHERE key = ?1", [key])
+                .map_err(sql)?;
+            transaction.commit().map_err(sql)
+        })
+        .await
+    }
+
+    /// Done in one transaction.
+    async fn rename_ref(&self, from: &str, to: &str) -> object_rainbow::Result<()> {
+        let from = from.to_owned();
+        let to = to.to_owned();
+        self.with(move |connection| {
+            let transaction = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(sql)?;
+            if fetch_ref(&transaction, &from)?.get().is_none() {
+                return Err(object_rainbow::Error::HashNotFound);
+            }
+            check_ref(&transaction, &to, OptionalHash::NONE)?;
+            transaction
+                .execute("UPDATE refs SET key = ?2 WHERE key = ?1", [from, to])
+                .map_err(sql)?;
+            transaction.commit().map_err(sql)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use futures_util::TryStreamExt;
+    use macro_rules_attribute::apply;
+    use object_rainbow::{Fetch, FullHash, OptionalHash, Singular};
+    use object_rainbow_point::{IntoPoint, Point};
+    use object_rainbow_store::{RainbowStore, RainbowStoreMut, StoreMut};
+    use smol_macros::test;
+
+    use crate::SqliteStore;
+
+    #[apply(test!)]
+    async fn objects() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("objects");
+        let store = SqliteStore::open(&path)?;
+        let point = ((*b"abc").point(), *b"def").point();
+        store.save_point(&point).await?;
+        assert!(store.contains(point.hash()).await?);
+        let store = SqliteStore::open(&path)?;
+        let (abc, def) = store
+            .point::<(Point<[u8; 3]>, [u8; 3])>(point.hash())
+            .fetch()
+            .await?;
+        assert_eq!(abc.fetch().await?, *b"abc");
+        assert_eq!(def, *b"def");
+        let objects = [*b"x", *b"y", *b"z"];
+        store
+            .save_many(objects.iter().map(|object| object.with_hash()).collect())
+            .await?;
+        assert_eq!(
+            store
+                .contains_many(&[
+                    b"x".full_hash(),
+                    b"w".full_hash(),
+                    b"z".full_hash(),
+                    abc.hash(),
+                ])
+                .await?,
+            [true, false, true, true],
+        );
+        let listed = store.list_objects().try_collect::<Vec<_>>().await?;
+        assert_eq!(listed.len(), 5);
+        assert!(listed.iter().all(|(_, written)| written.is_some()));
+        store.delete(&[b"x".full_hash(), abc.hash()]).await?;
+        assert!(!store.contains(abc.hash()).await?);
+        assert_eq!(store.list_objects().try_collect::<Vec<_>>().await?.len(), 3);
+        store.save_raw(abc.hash(), b"abc").await?;
+        assert_eq!(store.point::<[u8; 3]>(abc.hash()).fetch().await?, *b"abc");
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn legacy_table() -> object_rainbow::Result<()> {
+        let connection = rusqlite::Connection::open_in_memory().map_err(crate::sql)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE objects (
+                    hash BLOB PRIMARY KEY,
+                    data BLOB NOT NULL
+                ) WITHOUT ROWID;",
+            )
+            .map_err(crate::sql)?;
+        connection
+            .execute(
+                "INSERT INTO objects (hash, data) VALUES (?1, ?2)",
+                rusqlite::params![b"abc".full_hash().as_slice(), b"abc"],
+            )
+            .map_err(crate::sql)?;
+        let store = SqliteStore::from_connection(connection)?;
+        store.save_point(&(*b"def").point()).await?;
+        let mut listed = store.list_objects().try_collect::<Vec<_>>().await?;
+        listed.sort_by_key(|(_, written)| *written);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0], (b"abc".full_hash(), None));
+        assert!(listed[1].1.is_some());
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn refs() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("refs");
+        let store = SqliteStore::open(&path)?;
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        assert_eq!(store.fetch_ref("main").await?, OptionalHash::NONE);
+        assert!(!store.ref_exists("main").await?);
+        store
+            .update_ref("main", Some(OptionalHash::NONE), a)
+            .await?;
+        assert!(
+            store
+                .update_ref("main", Some(OptionalHash::NONE), b)
+                .await
+                .is_err()
+        );
+        store.update_ref("main", Some(a.into()), b).await?;
+        assert_eq!(store.fetch_ref("main").await?, b);
+        assert!(store.update_ref("main", Some(a.into()), a).await.is_err());
+        store.update_ref("main", None, a).await?;
+        assert_eq!(store.fetch_ref("main").await?, a);
+        assert!(store.ref_exists("main").await?);
+        let stored = StoreMut::new(store.clone())
+            .init("test", (*b"c").point())
+            .await?;
+        let loaded = StoreMut::new(SqliteStore::open(&path)?)
+            .load::<[u8; 1], _>("test")
+            .await?;
+        assert_eq!(loaded.hash(), stored.hash());
+        assert_eq!(loaded.fetch().await?, *b"c");
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn transaction() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("transaction");
+        let store = StoreMut::new(SqliteStore::open(&path)?);
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        let mut transaction = store.transaction();
+        transaction
+            .update("table", Some(OptionalHash::NONE), a)
+            .update("index", Some(OptionalHash::NONE), a);
+        transaction.commit().await?;
+        let mut transaction = store.transaction();
+        transaction
+            .update("table", Some(a.into()), b)
+            .update("index", Some(b.into()), b)
+            .update("other", Some(a.into()), b);
+        let error = transaction.commit().await.unwrap_err().to_string();
+        assert!(error.contains("index") && error.contains("other") && !error.contains("table"));
+        let store = SqliteStore::open(&path)?;
+        assert_eq!(store.fetch_ref("table").await?, a);
+        assert!(!store.ref_exists("other").await?);
+        Ok(())
+    }
+
+    #[apply(test!)]
+    async fn list_delete_rename() -> object_rainbow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("list_delete_rename");
+        let store = SqliteStore::open(&path)?;
+        let a = b"a".full_hash();
+        let b = b"b".full_hash();
+        store.update_ref("heads/main", None, a).await?;
+        store.update_ref("heads/dev", None, b).await?;
+        store.update_ref("heads_", None, b).await?;
+        store.update_ref("tags/v1", None, a).await?;
+        assert_eq!(
+            store.list_refs("heads/").try_collect::<Vec<_>>().await?,
+            [("heads/dev".into(), b), ("heads/main".into(), a)],
+        );
+        assert_eq!(store.list_refs("").try_collect::<Vec<_>>().await?.len(), 4);
+        assert!(store.delete_ref("tags/v1", Some(b.into())).await.is_err());
+        store.delete_ref("tags/v1", Some(a.into())).await?;
+        store.delete_ref("tags/v1", None).await?;
+        assert!(!store.ref_exists("tags/v1").await?);
+        assert!(store.rename_ref("heads/dev", "heads/main").await.is_err());
+        store.rename_ref("heads/dev", "heads/feature").await?;
+        assert_eq!(store.fetch_ref("heads/dev").await?, OptionalHash::NONE);
+        assert_eq!(store.fetch_ref("heads/feature").await?, b);
+        assert!(store.rename_ref("heads/dev", "heads/other").await.is_err());
+        Ok(())
+    }
+}
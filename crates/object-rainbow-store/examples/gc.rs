@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use object_rainbow::{Fetch, Singular};
+use object_rainbow_point::{IntoPoint, Point};
+use object_rainbow_store::{Gc, MemoryStore, RainbowStore, RainbowStoreMut, ReflogStore, StoreMut};
+
+type Tree = (Point<[u8; 3]>, [u8; 3]);
+
+fn main() -> object_rainbow::Result<()> {
+    smol::block_on(async {
+        let store = ReflogStore::new(MemoryStore::new()).with_max_entries(1);
+        let refs = StoreMut::new(store.clone());
+        let first = ((*b"abc").point(), *b"def").point();
+        let second = ((*b"ghi").point(), *b"jkl").point();
+        let third = ((*b"mno").point(), *b"pqr").point();
+        refs.init("main", first.clone()).await?;
+        refs.update("main", second.clone()).await?;
+        refs.update("main", third.clone()).await?;
+        let orphan = (*b"xyz").point();
+        store.save_point(&orphan).await?;
+        let gc = || {
+            Gc::new(store.clone())
+                .with_grace_period(Duration::ZERO)
+                .refs::<Tree>("")
+        };
+        assert!(Gc::new(store.clone()).run().await.is_err());
+        // the orphan, the first tree with its point, and two replaced reflogs
+        let report = gc().with_dry_run(true).run().await?;
+        assert_eq!(report.swept.len(), 5);
+        assert!(store.contains(orphan.hash()).await?);
+        let recent = Gc::new(store.clone()).refs::<Tree>("").run().await?;
+        assert_eq!(recent.recent, 5);
+        assert!(recent.swept.is_empty());
+        assert_eq!(gc().run().await?.swept.len(), 5);
+        for hash in [orphan.hash(), first.hash(), first.fetch().await?.0.hash()] {
+            assert!(!store.contains(hash).await?);
+        }
+        for tree in [second, third] {
+            let (a, b) = store.point::<Tree>(tree.hash()).fetch().await?;
+            assert_eq!(a.fetch().await?, tree.fetch().await?.0.fetch().await?);
+            assert_eq!(b, tree.fetch().await?.1);
+        }
+        assert_eq!(refs.reflog("main").await?.len(), 1);
+        assert!(gc().run().await?.swept.is_empty());
+        // a deleted ref keeps its tree through the reflog
+        let store = ReflogStore::new(MemoryStore::new());
+        let refs = StoreMut::new(store.clone());
+        let tree = ((*b"stu").point(), *b"vwx").point();
+        refs.init("main", tree.clone()).await?;
+        store.delete_ref("main", None).await?;
+        let report = Gc::new(store.clone())
+            .with_grace_period(Duration::ZERO)
+            .refs::<Tree>("")
+            .run()
+            .await?;
+        // only the replaced reflog
+        assert_eq!(report.swept.len(), 1);
+        let log = refs.reflog("main").await?;
+        refs.restore("main", &log[0]).await?;
+        let (a, b) = refs.load::<Tree, _>("main").await?.fetch().await?;
+        assert_eq!(a.fetch().await?, *b"stu");
+        assert_eq!(b, *b"vwx");
+        Ok(())
+    })
+}
@@ -0,0 +1,31 @@
+use object_rainbow::{Fetch, OptionalHash, Singular};
+use object_rainbow_point::IntoPoint;
+use object_rainbow_store::{MemoryStore, RainbowStoreMut, ReflogStore, StoreMut};
+
+fn main() -> object_rainbow::Result<()> {
+    smol::block_on(async {
+        let store = StoreMut::new(ReflogStore::new(MemoryStore::new()).with_max_entries(3));
+        let mut main = store.init("main", (*b"good").point()).await?;
+        let good = main.hash();
+        *main.fetch_mut().await? = *b"oops";
+        main.save().await?;
+        let log = store.reflog("main").await?;
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].old, OptionalHash::NONE);
+        assert_eq!(log[1].old, good);
+        store.restore("main", &log[0]).await?;
+        let main = store.load::<[u8; 4], _>("main").await?;
+        assert_eq!(main.fetch().await?, *b"good");
+        store.update("main", (*b"next").point()).await?;
+        let log = store.reflog("main").await?;
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[2].old, good);
+        let inner = MemoryStore::new();
+        let store = ReflogStore::new(inner.clone());
+        store.update_ref("main", None, good).await?;
+        store.delete_ref("main", Some(good.into())).await?;
+        assert!(!inner.ref_exists("refs/main").await?);
+        assert_eq!(store.reflog("main").await?[1].new, OptionalHash::NONE);
+        Ok(())
+    })
+}
@@ -0,0 +1,114 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use object_rainbow::{Fetch, Hash, Singular, ToOutput, WithHash};
+use object_rainbow_point::{IntoPoint, Point};
+use object_rainbow_store::{MemoryStore, RainbowStore};
+
+/// Counts requests, which take a while each, to see how many overlap.
+#[derive(Clone, Default)]
+struct Store {
+    store: MemoryStore,
+    stats: Arc<Stats>,
+}
+
+impl PartialEq for Store {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.stats, &other.stats)
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+    checks: AtomicUsize,
+    saved: Mutex<Vec<Hash>>,
+}
+
+impl Store {
+    async fn request<T>(&self, f: impl Future<Output = T>) -> T {
+        let in_flight = self.stats.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.stats
+            .max_in_flight
+            .fetch_max(in_flight, Ordering::SeqCst);
+        smol::Timer::after(Duration::from_millis(1)).await;
+        let output = f.await;
+        self.stats.in_flight.fetch_sub(1, Ordering::SeqCst);
+        output
+    }
+}
+
+impl RainbowStore for Store {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        self.stats.saved.lock().unwrap().push(wh.data_hash());
+        self.request(self.store.save_data(wh)).await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        self.stats.checks.fetch_add(1, Ordering::SeqCst);
+        self.request(self.store.contains(hash)).await
+    }
+
+    fn fetch(
+        &self,
+        hash: Hash,
+    ) -> impl Send + Future<Output = object_rainbow::Result<impl 'static + Send + Sync + AsRef<[u8]>>>
+    {
+        self.store.fetch(hash)
+    }
+
+    fn concurrency(&self) -> usize {
+        4
+    }
+}
+
+type Tree = Vec<Point<(Point<[u8; 3]>, u8)>>;
+
+type Wide = Vec<Point<Vec<Point<(u8, u8)>>>>;
+
+fn main() -> object_rainbow::Result<()> {
+    smol::block_on(async {
+        let store = Store::default();
+        let shared = (*b"abc").point();
+        let tree: Tree = (0..50).map(|i| (shared.clone(), i).point()).collect();
+        let tree = tree.point();
+        store.save_point(&tree).await?;
+        assert!(store.stats.max_in_flight.load(Ordering::SeqCst) <= store.concurrency());
+        // the root, then its 50 points, then `shared` once
+        assert_eq!(store.stats.checks.load(Ordering::SeqCst), 52);
+        let mut saved = store.stats.saved.lock().unwrap().clone();
+        assert_eq!(saved.len(), 52);
+        saved.sort();
+        saved.dedup();
+        assert_eq!(saved.len(), 52);
+        for node in tree.fetch().await? {
+            assert!(store.contains(node.hash()).await?);
+        }
+        assert!(store.contains(shared.hash()).await?);
+        // several wide levels saved at once still share the same limit
+        let store = Store::default();
+        let wide: Wide = (0..8)
+            .map(|i| (0..50).map(|j| (i, j).point()).collect::<Vec<_>>().point())
+            .collect();
+        let wide = wide.point();
+        store.save_point(&wide).await?;
+        assert!(store.stats.max_in_flight.load(Ordering::SeqCst) <= store.concurrency());
+        // the root, its 8 points, then 50 points of each
+        assert_eq!(store.stats.checks.load(Ordering::SeqCst), 1 + 8 + 8 * 50);
+        for node in wide.fetch().await? {
+            for leaf in node.fetch().await? {
+                assert!(store.contains(leaf.hash()).await?);
+            }
+        }
+        Ok(())
+    })
+}
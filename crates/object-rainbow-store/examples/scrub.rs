@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use object_rainbow::{Fetch, Hash, Singular, ToOutput, WithHash};
+use object_rainbow_point::{IntoPoint, Point};
+use object_rainbow_store::{
+    Damage, ExternalStore, MemoryStore, RainbowStore, RainbowStoreMut, Scrub, StoreMut,
+};
+
+type Tree = (Point<[u8; 3]>, Point<[u8; 3]>);
+
+#[derive(Clone, Default)]
+struct External(DashMap<Hash, Vec<u8>>, Arc<()>);
+
+impl PartialEq for External {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.1, &other.1)
+    }
+}
+
+impl ExternalStore for External {
+    type Id = Hash;
+
+    async fn save_data(
+        &self,
+        data: &[u8],
+        _: &[Self::Id],
+        wh: WithHash<'_, impl ToOutput>,
+    ) -> object_rainbow::Result<Self::Id> {
+        let id = wh.data_hash();
+        self.0.insert(id, data.into());
+        Ok(id)
+    }
+
+    async fn contains_data(
+        &self,
+        _: &[u8],
+        _: &[Self::Id],
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<bool> {
+        self.contains(&wh.data_hash()).await
+    }
+
+    async fn contains(&self, id: &Self::Id) -> object_rainbow::Result<bool> {
+        Ok(self.0.contains_key(id))
+    }
+
+    async fn fetch(
+        &self,
+        id: &Self::Id,
+    ) -> object_rainbow::Result<impl 'static + Send + Sync + AsRef<[u8]>> {
+        self.0
+            .get(id)
+            .ok_or(object_rainbow::Error::HashNotFound)
+            .map(|r| r.clone())
+    }
+}
+
+fn main() -> object_rainbow::Result<()> {
+    smol::block_on(async {
+        let replica = MemoryStore::new();
+        let store = MemoryStore::new();
+        let tree = ((*b"abc").point(), (*b"def").point()).point();
+        replica.save_point(&tree).await?;
+        StoreMut::new(store.clone())
+            .init("main", tree.clone())
+            .await?;
+        let (a, b) = tree.fetch().await?;
+        let scrub = || Scrub::new(store.clone()).refs::<Tree>("");
+        let report = scrub().run().await?;
+        assert!(report.is_clean());
+        assert_eq!((report.listed, report.walked), (3, 3));
+
+        store.delete(&[a.hash()]).await?;
+        store.save_raw(a.hash(), b"ab").await?;
+        store.delete(&[b.hash()]).await?;
+        // `MemoryStore` doesn't check hashes, so without types none of it shows up
+        let report = Scrub::new(store.clone()).run().await?;
+        assert_eq!(report.listed, 2);
+        assert!(report.is_clean());
+        // a replica with damage of its own doesn't get anything saved from it
+        let damaged = MemoryStore::new();
+        damaged.save_raw(a.hash(), b"abd").await?;
+        let report = scrub().with_replica(damaged).run().await?;
+        assert_eq!(report.damaged.len(), 2);
+        assert!(report.damaged.iter().all(|damaged| !damaged.repaired));
+        assert_eq!(&*store.fetch(a.hash()).await?, b"ab");
+        assert!(!store.contains(b.hash()).await?);
+        let report = scrub().with_replica(replica.clone()).run().await?;
+        assert_eq!(report.walked, 1);
+        assert_eq!(report.damaged.len(), 2);
+        for damaged in &report.damaged {
+            assert_eq!(damaged.referrer, Some(tree.hash()));
+            assert!(damaged.repaired);
+            let damage = if damaged.id == a.hash() {
+                Damage::Truncated
+            } else {
+                Damage::Missing
+            };
+            assert_eq!(damaged.damage, damage);
+        }
+        assert!(scrub().run().await?.is_clean());
+        assert_eq!(store.fetch_ref("main").await?, tree.hash());
+
+        let external = External::default();
+        let id = external
+            .store_object(((*b"abc").point(), (*b"def").point()))
+            .await?;
+        let report = external.scrub::<Tree>(&id).await?;
+        assert!(report.is_clean());
+        assert_eq!(report.walked, 3);
+        let report = external.scrub::<([u8; 3], [u8; 3])>(&id).await?;
+        assert_eq!(report.damaged[0].damage, Damage::Corrupt);
+        external.0.retain(|key, _| *key == id);
+        let report = external.scrub::<Tree>(&id).await?;
+        assert_eq!(report.walked, 1);
+        assert_eq!(report.damaged.len(), 2);
+        for damaged in &report.damaged {
+            assert_eq!(damaged.damage, Damage::Missing);
+            assert_eq!(damaged.referrer, Some(id));
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,66 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use object_rainbow::{Fetch, OptionalHash, Singular};
+use object_rainbow_point::{IntoPoint, Point};
+use object_rainbow_store::{
+    MemoryStore, RainbowStore, RainbowStoreMut, StoreSync, SyncProgress, sync,
+};
+
+type Tree = (Point<[u8; 3]>, Point<[u8; 3]>);
+
+fn main() -> object_rainbow::Result<()> {
+    smol::block_on(async {
+        let src = MemoryStore::new();
+        let dst = MemoryStore::new();
+        let shared = (*b"abc").point();
+        let first = (shared.clone(), shared.clone()).point();
+        src.save_point(&first).await?;
+        let progress = sync::<Tree>(&src, &dst, first.hash()).await?;
+        assert_eq!(
+            progress,
+            SyncProgress {
+                checked: 2,
+                skipped: 0,
+                copied: 2,
+            },
+        );
+        let second = (shared.clone(), (*b"def").point()).point();
+        src.save_point(&second).await?;
+        let reported = Arc::new(AtomicUsize::new(0));
+        let progress = StoreSync::new(src.clone(), dst.clone())
+            .with_concurrency(1)
+            .with_progress({
+                let reported = reported.clone();
+                move |_| {
+                    reported.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .run_to_ref::<Tree>(second.hash(), "main", Some(OptionalHash::NONE))
+            .await?;
+        assert_eq!(
+            progress,
+            SyncProgress {
+                checked: 3,
+                skipped: 1,
+                copied: 2,
+            },
+        );
+        assert_eq!(reported.load(Ordering::Relaxed), 5);
+        assert_eq!(dst.fetch_ref("main").await?, second.hash());
+        let (a, b) = dst.point::<Tree>(second.hash()).fetch().await?;
+        assert_eq!(a.fetch().await?, *b"abc");
+        assert_eq!(b.fetch().await?, *b"def");
+        let progress = sync::<Tree>(&src, &dst, second.hash()).await?;
+        assert_eq!(progress.skipped, 1);
+        assert_eq!(progress.copied, 0);
+        assert!(
+            sync::<Tree>(&src, &dst, (*b"xyz").point().hash())
+                .await
+                .is_err()
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,68 @@
+use futures_util::TryStreamExt;
+use object_rainbow::{Fetch, Singular};
+use object_rainbow_point::{IntoPoint, Point};
+use object_rainbow_store::{
+    MemoryStore, RainbowStore, RainbowStoreMut, StoreMut, TieredStore, WritePolicy,
+};
+
+type Tree = (Point<[u8; 3]>, Point<[u8; 3]>);
+
+fn main() -> object_rainbow::Result<()> {
+    smol::block_on(async {
+        let slow = MemoryStore::new();
+        let store = TieredStore::new(MemoryStore::new(), slow.clone()).with_max_objects(2);
+        let first = ((*b"abc").point(), (*b"def").point()).point();
+        store.save_point(&first).await?;
+        let (a, b) = first.fetch().await?;
+        for hash in [a.hash(), b.hash(), first.hash()] {
+            assert!(slow.contains(hash).await?);
+        }
+        // `a` was saved first, so it's been evicted
+        assert!(!store.fast().contains(a.hash()).await?);
+        assert!(store.fast().contains(first.hash()).await?);
+        let (a, b) = store.point::<Tree>(first.hash()).fetch().await?;
+        assert_eq!(a.fetch().await?, *b"abc");
+        assert_eq!(b.fetch().await?, *b"def");
+        assert!(store.fast().contains(a.hash()).await?);
+
+        let store = TieredStore::new(MemoryStore::new(), slow.clone())
+            .with_write_policy(WritePolicy::Back)
+            .with_max_bytes(0);
+        let second = ((*b"ghi").point(), (*b"jkl").point()).point();
+        store.save_point(&second).await?;
+        assert!(!slow.contains(second.hash()).await?);
+        assert!(store.contains(second.hash()).await?);
+        StoreMut::new(store.clone())
+            .init("main", second.clone())
+            .await?;
+        assert_eq!(slow.fetch_ref("main").await?, second.hash());
+        assert!(!store.fast().contains(second.hash()).await?);
+        let (a, b) = slow.point::<Tree>(second.hash()).fetch().await?;
+        assert_eq!(a.fetch().await?, *b"ghi");
+        assert_eq!(b.fetch().await?, *b"jkl");
+        assert_eq!(store.fetch_ref("main").await?, second.hash());
+
+        // unflushed objects outlive the store that saved them
+        let fast = MemoryStore::new();
+        let store = TieredStore::new(fast.clone(), slow.clone())
+            .with_write_policy(WritePolicy::Back)
+            .with_max_bytes(0);
+        let third = ((*b"mno").point(), (*b"pqr").point()).point();
+        store.save_point(&third).await?;
+        drop(store);
+        let store = TieredStore::new(fast.clone(), slow.clone())
+            .with_write_policy(WritePolicy::Back)
+            .with_max_bytes(0);
+        store.save_point(&third).await?;
+        assert!(!slow.contains(third.hash()).await?);
+        StoreMut::new(store.clone())
+            .init("third", third.clone())
+            .await?;
+        let (a, b) = slow.point::<Tree>(third.hash()).fetch().await?;
+        assert_eq!(a.fetch().await?, *b"mno");
+        assert_eq!(b.fetch().await?, *b"pqr");
+        assert!(!fast.contains(third.hash()).await?);
+        assert_eq!(fast.list_refs("").try_collect::<Vec<_>>().await?, []);
+        Ok(())
+    })
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashSet, pin::Pin, sync::Arc};
 
 use object_rainbow::{
     Address, Error, Hash, InlineOutput, Parse, ParseInline, ParseInput, ParseSlice,
@@ -7,7 +7,7 @@ use object_rainbow::{
 };
 use object_rainbow_point::ExtractResolve;
 
-use crate::ExternalStore;
+use crate::{ExternalStore, ScrubReport};
 
 #[derive(ToOutput, InlineOutput, Parse, ParseInline)]
 struct Header<Id> {
@@ -182,3 +182,102 @@ pub(crate) async fn load<S: ExternalStore, T: ParseSlice + Tagged>(
 ) -> object_rainbow::Result<T> {
     load_extra(store, id, ()).await
 }
+
+type Scrubbing<'s, Id> =
+    Pin<Box<dyn 's + Send + Future<Output = object_rainbow::Result<Vec<ScrubChild<'s, Id>>>>>>;
+
+struct ScrubChild<'s, Id> {
+    id: Id,
+    scrubbing: Scrubbing<'s, Id>,
+}
+
+struct ScrubChildren<'a, 's, S: ExternalStore> {
+    children: &'a mut Vec<ScrubChild<'s, S::Id>>,
+    store: &'s S,
+}
+
+impl<S: ExternalStore> PointVisitor for ScrubChildren<'_, '_, S> {
+    fn visit(&mut self, point: &(impl 'static + SingularFetch<T: Traversible> + Clone)) {
+        if let Some((address, resolve)) = point.extract_resolve::<ExternalResolve<S>>()
+            && let Ok(id) = resolve.translate(*address)
+        {
+            self.children.push(ScrubChild {
+                id: id.clone(),
+                scrubbing: Box::pin(scrub_point(self.store, id, point.clone())),
+            });
+        }
+    }
+}
+
+/// Check that the object stored under `id` has a header with the right tags.
+async fn scrub_header<S: ExternalStore>(
+    store: &S,
+    id: &S::Id,
+    tags: Hash,
+) -> object_rainbow::Result<Header<S::Id>> {
+    let Raw { header, .. } = Raw::<S::Id>::parse_slice_refless(store.fetch(id).await?.as_ref())?;
+    if header.tags != tags {
+        return Err(object_rainbow::error_consistency!("tags mismatch"));
+    }
+    Ok(header)
+}
+
+/// Check that the header lists as many ids as `object` has points, and collect those.
+fn scrub_topology<'s, S: ExternalStore>(
+    store: &'s S,
+    header: &Header<S::Id>,
+    object: &impl Traversible,
+) -> object_rainbow::Result<Vec<ScrubChild<'s, S::Id>>> {
+    if header.topology.len() != object.point_count() {
+        return Err(object_rainbow::error_consistency!(
+            "topology length mismatch"
+        ));
+    }
+    let mut children = Vec::with_capacity(object.point_count());
+    object.traverse(&mut ScrubChildren {
+        children: &mut children,
+        store,
+    });
+    Ok(children)
+}
+
+async fn scrub_point<S: ExternalStore, P: 'static + SingularFetch<T: Traversible>>(
+    store: &S,
+    id: S::Id,
+    point: P,
+) -> object_rainbow::Result<Vec<ScrubChild<'_, S::Id>>> {
+    let header = scrub_header(store, &id, <P::T as Tagged>::HASH).await?;
+    let object = point.fetch().await?;
+    scrub_topology(store, &header, &object)
+}
+
+pub(crate) async fn scrub<S: ExternalStore, T: ParseSlice + Traversible>(
+    store: &S,
+    id: &S::Id,
+) -> object_rainbow::Result<ScrubReport<S::Id>> {
+    let root: Scrubbing<'_, S::Id> = Box::pin(async move {
+        let header = scrub_header(store, id, T::HASH).await?;
+        let object = load::<S, T>(store, id).await?;
+        scrub_topology(store, &header, &object)
+    });
+    let mut report = ScrubReport::default();
+    let mut stack = vec![(id.clone(), root, None)];
+    let mut walked = HashSet::new();
+    while let Some((id, scrubbing, referrer)) = stack.pop() {
+        if !walked.insert(id.vec()) {
+            continue;
+        }
+        match scrubbing.await {
+            Ok(children) => {
+                report.walked += 1;
+                stack.extend(
+                    children
+                        .into_iter()
+                        .map(|child| (child.id, child.scrubbing, Some(id.clone()))),
+                );
+            }
+            Err(e) => report.damaged(id, referrer, e)?,
+        }
+    }
+    Ok(report)
+}
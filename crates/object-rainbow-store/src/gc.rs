@@ -0,0 +1,251 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::TryStreamExt;
+use object_rainbow::{
+    Hash, ListHashes, Object, PointVisitor, SingularFetch, Topological, Traversible,
+};
+
+use crate::{RainbowStore, RainbowStoreMut};
+
+pub(crate) type Pending = Pin<Box<dyn Send + Future<Output = object_rainbow::Result<Node>>>>;
+
+/// Fetches an object as its type whenever called, so it can be checked again later.
+pub(crate) type Walk = Arc<dyn Send + Sync + Fn() -> Pending>;
+
+type RefRoot = Arc<dyn Send + Sync + Fn(Hash) -> Pending>;
+
+/// What walking needs from a fetched object: hashes it mentions, and points to descend into.
+pub(crate) struct Node {
+    pub(crate) hashes: Vec<Hash>,
+    pub(crate) children: Vec<(Hash, Walk)>,
+}
+
+struct Children(Vec<(Hash, Walk)>);
+
+impl PointVisitor for Children {
+    fn visit(&mut self, point: &(impl 'static + SingularFetch<T: Traversible> + Clone)) {
+        self.0.push((point.hash(), walk(point.clone())));
+    }
+}
+
+pub(crate) fn walk(point: impl 'static + SingularFetch<T: Traversible> + Clone) -> Walk {
+    Arc::new(move || pending(point.clone()))
+}
+
+pub(crate) fn pending(point: impl 'static + SingularFetch<T: Traversible>) -> Pending {
+    Box::pin(async move {
+        let object = point.fetch().await?;
+        let mut hashes = Vec::new();
+        object.list_hashes(&mut |hash| hashes.push(hash));
+        let mut children = Children(Vec::new());
+        object.traverse(&mut children);
+        Ok(Node {
+            hashes,
+            children: children.0,
+        })
+    })
+}
+
+/// Typed refs and extra roots to walk from, see [`Gc::refs`] and [`Gc::root`].
+#[derive(Default)]
+pub(crate) struct Roots {
+    refs: Vec<(String, RefRoot)>,
+    pub(crate) extra: Vec<(Hash, Walk)>,
+}
+
+impl Roots {
+    pub(crate) fn refs<T: Object<Extra>, Extra: 'static + Send + Sync + Clone>(
+        &mut self,
+        store: &impl RainbowStore,
+        prefix: &str,
+        extra: Extra,
+    ) {
+        let store = store.clone();
+        self.refs.push((
+            prefix.to_owned(),
+            Arc::new(move |hash| pending(store.point_extra::<T, Extra>(hash, extra.clone()))),
+        ));
+    }
+
+    pub(crate) fn root(&mut self, point: impl 'static + SingularFetch<T: Traversible> + Clone) {
+        self.extra.push((point.hash(), walk(point)));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.refs.is_empty() && self.extra.is_empty()
+    }
+
+    /// `None` if no type has been declared for `key`.
+    pub(crate) fn ref_root(&self, key: &str, hash: Hash) -> Option<(Hash, Walk)> {
+        let (_, root) = self
+            .refs
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())?;
+        let root = root.clone();
+        Some((hash, Arc::new(move || root(hash))))
+    }
+}
+
+/// Outcome of a [`Gc`] run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Hashes reached from the roots.
+    pub marked: usize,
+    /// Unreachable objects that have been deleted, or would have been outside of a dry run.
+    pub swept: Vec<Hash>,
+    /// Unreachable objects kept because they were written within the grace period.
+    pub recent: usize,
+    /// Unreachable objects kept because the store doesn't know when they were written, see
+    /// [`Gc::with_sweep_untimed`].
+    pub untimed: usize,
+}
+
+/// Mark-and-sweep garbage collection for a [`RainbowStoreMut`].
+///
+/// Refs only hold hashes, so the type behind them has to be declared with [`Gc::refs`], and a run
+/// fails if it comes across a ref with no type declared. Marking starts from every ref, every
+/// [`RainbowStoreMut::reflog`] entry of those refs and of [`RainbowStoreMut::list_reflogs`], so
+/// that deleted refs can still be restored, and every [`Gc::root`]. It fetches objects
+/// through the store's [`Resolve`](object_rainbow::Resolve), descends into their points, and also
+/// keeps whatever other hashes they list.
+///
+/// Only objects listed before marking starts are swept, and only if they were written before the
+/// grace period preceding the run. That covers objects a concurrent writer has just saved, before
+/// a ref pointing to them has been updated. It doesn't cover ones the writer found already stored
+/// and skipped, along with their subtrees, as that doesn't change when they were written: if they
+/// were unreachable and older than the grace period, they can be swept from under a ref that is
+/// about to point to them. Don't run with writers that may reuse old unreachable objects, like
+/// ones restoring a deleted ref's tree from elsewhere. Objects with no known write time are kept,
+/// unless [`Gc::with_sweep_untimed`] says otherwise.
+pub struct Gc<S> {
+    store: S,
+    roots: Roots,
+    grace_period: Duration,
+    dry_run: bool,
+    sweep_untimed: bool,
+}
+
+impl<S: RainbowStoreMut> Gc<S> {
+    /// By default, the grace period is one hour.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            roots: Roots::default(),
+            grace_period: Duration::from_secs(3600),
+            dry_run: false,
+            sweep_untimed: false,
+        }
+    }
+
+    pub fn with_grace_period(self, grace_period: Duration) -> Self {
+        Self {
+            grace_period,
+            ..self
+        }
+    }
+
+    /// Only report what would be swept.
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Also sweep unreachable objects the store doesn't know the write time of, which leaves them
+    /// with only the first guarantee: nothing saved after listing is swept, but an object saved
+    /// just before that, for a ref that hasn't been updated yet, can be.
+    pub fn with_sweep_untimed(self, sweep_untimed: bool) -> Self {
+        Self {
+            sweep_untimed,
+            ..self
+        }
+    }
+
+    /// Refs starting with `prefix` point to `T`s. The longest matching prefix wins.
+    pub fn refs<T: Object>(self, prefix: &str) -> Self {
+        self.refs_extra::<T, ()>(prefix, ())
+    }
+
+    pub fn refs_extra<T: Object<Extra>, Extra: 'static + Send + Sync + Clone>(
+        mut self,
+        prefix: &str,
+        extra: Extra,
+    ) -> Self {
+        self.roots.refs::<T, Extra>(&self.store, prefix, extra);
+        self
+    }
+
+    /// Also keep everything reachable from `point`.
+    pub fn root(mut self, point: impl 'static + SingularFetch<T: Traversible> + Clone) -> Self {
+        self.roots.root(point);
+        self
+    }
+
+    fn ref_root(&self, key: &str, hash: Hash) -> object_rainbow::Result<(Hash, Walk)> {
+        self.roots
+            .ref_root(key, hash)
+            .ok_or_else(|| object_rainbow::error_operation!("no type declared for ref {key:?}"))
+    }
+
+    pub async fn run(self) -> object_rainbow::Result<GcReport> {
+        let cutoff = SystemTime::now()
+            .checked_sub(self.grace_period)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let listed = self.store.list_objects().try_collect::<Vec<_>>().await?;
+        let refs = self.store.list_refs("").try_collect::<Vec<_>>().await?;
+        let mut logged = self
+            .store
+            .list_reflogs()
+            .try_collect::<BTreeSet<_>>()
+            .await?;
+        let mut stack = Vec::new();
+        for (key, hash) in &refs {
+            stack.push(self.ref_root(key, *hash)?);
+        }
+        logged.extend(refs.into_iter().map(|(key, _)| key));
+        for key in logged {
+            for entry in self.store.reflog(&key).await? {
+                for hash in [entry.old, entry.new]
+                    .into_iter()
+                    .filter_map(|hash| hash.get())
+                {
+                    stack.push(self.ref_root(&key, hash)?);
+                }
+            }
+        }
+        stack.extend(self.roots.extra);
+        let mut traversed = HashSet::new();
+        let mut marked = HashSet::new();
+        while let Some((hash, walk)) = stack.pop() {
+            marked.insert(hash);
+            if !traversed.insert(hash) {
+                continue;
+            }
+            let Node { hashes, children } = walk().await?;
+            marked.extend(hashes);
+            stack.extend(children);
+        }
+        let mut report = GcReport {
+            marked: marked.len(),
+            ..Default::default()
+        };
+        for (hash, written) in listed {
+            if marked.contains(&hash) {
+                continue;
+            }
+            match written {
+                None if !self.sweep_untimed => report.untimed += 1,
+                Some(written) if written >= cutoff => report.recent += 1,
+                _ => report.swept.push(hash),
+            }
+        }
+        if !self.dry_run && !report.swept.is_empty() {
+            self.store.delete(&report.swept).await?;
+        }
+        Ok(report)
+    }
+}
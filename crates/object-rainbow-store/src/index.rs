@@ -0,0 +1,242 @@
+use std::{collections::HashMap, pin::pin, time::SystemTime};
+
+use futures_util::{Stream, TryStreamExt};
+use genawaiter_try_stream::try_stream;
+use hex::FromHex;
+use object_rainbow::{Hash, OptionalHash, ToOutput, WithHash};
+
+use crate::{RainbowStoreMut, RefUpdate, ReflogEntry};
+
+const OBJECTS: &str = "objects/";
+const REFS: &str = "refs/";
+
+/// Blobs kept in `S` under ids chosen by an adapter rather than under their own hashes, along
+/// with the adapter's refs. Meant for adapters that change data on its way into `S`, like
+/// encryption or compression, which can't store it under the hash of what it was.
+///
+/// Where `S` supports [`RainbowStore::save_raw`](crate::RainbowStore::save_raw), each blob is
+/// written under its id in one call. Otherwise it's saved as an object of its own, and then found
+/// through a ref named `objects/<id>`; all refs of one [`ObjectIndex::save_many`] go into a single
+/// [`RainbowStoreMut::update_refs`]. A failure between those two steps leaves a blob that isn't
+/// listed. The adapter's own refs are kept under `refs/<key>`.
+///
+/// Neither blobs nor index refs parse as the objects they stand for, so [`Gc`](crate::Gc) and
+/// [`Scrub`](crate::Scrub) are to be run on the adapter, through [`ObjectIndex::list`] and
+/// [`ObjectIndex::delete`], and not on `S`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectIndex<S> {
+    store: S,
+}
+
+impl<S> ObjectIndex<S> {
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Where the adapter's ref `key` is kept in `S`.
+    pub fn ref_key(key: &str) -> String {
+        format!("{REFS}{key}")
+    }
+
+    fn index_key(id: Hash) -> String {
+        format!("{OBJECTS}{}", hex::encode(id))
+    }
+}
+
+impl<S: RainbowStoreMut> ObjectIndex<S> {
+    pub async fn save(&self, id: Hash, blob: &[u8]) -> object_rainbow::Result<()> {
+        self.save_many(vec![(id, blob.to_vec())]).await
+    }
+
+    pub async fn save_many(&self, blobs: Vec<(Hash, Vec<u8>)>) -> object_rainbow::Result<()> {
+        let mut blobs = blobs.into_iter();
+        for (id, blob) in blobs.by_ref() {
+            match self.store.save_raw(id, &blob).await {
+                Err(object_rainbow::Error::Unimplemented) => {
+                    return self
+                        .save_indexed(std::iter::once((id, blob)).chain(blobs).collect())
+                        .await;
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn save_indexed(&self, blobs: Vec<(Hash, Vec<u8>)>) -> object_rainbow::Result<()> {
+        let wrapped = blobs
+            .iter()
+            .map(|(_, blob)| WithHash {
+                diff: Hash::default(),
+                data: blob,
+            })
+            .collect::<Vec<_>>();
+        let updates = blobs
+            .iter()
+            .zip(&wrapped)
+            .map(|((id, _), wh)| RefUpdate {
+                key: Self::index_key(*id),
+                old: None,
+                hash: wh.data_hash(),
+            })
+            .collect();
+        self.store.save_many(wrapped).await?;
+        self.store.update_refs(updates).await
+    }
+
+    /// Where the blob of `id` is in `S`, if it's there at all.
+    pub async fn locate(&self, id: Hash) -> object_rainbow::Result<Option<Hash>> {
+        if let Some(stored) = self.store.fetch_ref(&Self::index_key(id)).await?.get() {
+            Ok(Some(stored))
+        } else if self.store.contains(id).await? {
+            Ok(Some(id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn contains(&self, id: Hash) -> object_rainbow::Result<bool> {
+        Ok(self.store.contains(id).await? || self.store.ref_exists(&Self::index_key(id)).await?)
+    }
+
+    pub async fn fetch(&self, id: Hash) -> object_rainbow::Result<Vec<u8>> {
+        let stored = self
+            .locate(id)
+            .await?
+            .ok_or(object_rainbow::Error::HashNotFound)?;
+        Ok(self.store.fetch(stored).await?.as_ref().to_vec())
+    }
+
+    /// Ids of saved blobs, along with where each is in `S` and when it was written. Objects of `S`
+    /// that aren't index targets are listed under their own hashes, which can also be blobs left
+    /// behind by a failed save; the adapter can tell those apart by their content.
+    pub fn list(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Hash, Option<SystemTime>)>> {
+        try_stream(async move |co| {
+            let mut objects = self
+                .store
+                .list_objects()
+                .try_collect::<HashMap<_, _>>()
+                .await?;
+            let mut index = pin!(self.store.list_refs(OBJECTS));
+            while let Some((key, stored)) = index.try_next().await? {
+                let id = key
+                    .strip_prefix(OBJECTS)
+                    .and_then(|id| Hash::from_hex(id).ok())
+                    .ok_or_else(|| {
+                        object_rainbow::error_consistency!("invalid index ref {key:?}")
+                    })?;
+                if let Some(written) = objects.remove(&stored) {
+                    co.yield_((id, stored, written)).await;
+                }
+            }
+            for (hash, written) in objects {
+                co.yield_((hash, hash, written)).await;
+            }
+            Ok(())
+        })
+    }
+
+    /// Remove blobs along with their index refs. Ids that aren't saved are ignored.
+    pub async fn delete(&self, ids: &[Hash]) -> object_rainbow::Result<()> {
+        let mut stored = Vec::with_capacity(ids.len());
+        for &id in ids {
+            let key = Self::index_key(id);
+            match self.store.fetch_ref(&key).await?.get() {
+                Some(hash) => {
+                    self.store.delete_ref(&key, Some(hash.into())).await?;
+                    stored.push(hash);
+                }
+                None => stored.push(id),
+            }
+        }
+        self.store.delete(&stored).await
+    }
+
+    pub async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.store.update_ref(&Self::ref_key(key), old, hash).await
+    }
+
+    pub async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        let updates = updates
+            .into_iter()
+            .map(|update| RefUpdate {
+                key: Self::ref_key(&update.key),
+                ..update
+            })
+            .collect();
+        self.store.update_refs(updates).await
+    }
+
+    pub async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        self.store.fetch_ref(&Self::ref_key(key)).await
+    }
+
+    pub async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        self.store.ref_exists(&Self::ref_key(key)).await
+    }
+
+    pub fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        try_stream(async move |co| {
+            let prefix = Self::ref_key(prefix);
+            let mut refs = pin!(self.store.list_refs(&prefix));
+            while let Some((key, hash)) = refs.try_next().await? {
+                if let Some(key) = key.strip_prefix(REFS) {
+                    co.yield_((key.to_owned(), hash)).await;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub async fn reflog(&self, key: &str) -> object_rainbow::Result<Vec<ReflogEntry>> {
+        self.store.reflog(&Self::ref_key(key)).await
+    }
+
+    pub fn list_reflogs(&self) -> impl Send + Stream<Item = object_rainbow::Result<String>> {
+        self.store
+            .list_reflogs()
+            .try_filter_map(async |key| Ok(key.strip_prefix(REFS).map(str::to_owned)))
+    }
+
+    pub fn watch(
+        &self,
+        key: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        try_stream(async move |co| {
+            let key = Self::ref_key(key);
+            let mut hashes = pin!(self.store.watch(&key));
+            while let Some(hash) = hashes.try_next().await? {
+                co.yield_(hash).await;
+            }
+            Ok(())
+        })
+    }
+
+    pub async fn delete_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+    ) -> object_rainbow::Result<()> {
+        self.store.delete_ref(&Self::ref_key(key), old).await
+    }
+
+    pub async fn rename_ref(&self, from: &str, to: &str) -> object_rainbow::Result<()> {
+        self.store
+            .rename_ref(&Self::ref_key(from), &Self::ref_key(to))
+            .await
+    }
+}
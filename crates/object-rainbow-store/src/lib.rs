@@ -1,9 +1,10 @@
 use std::{
     ops::{Deref, DerefMut},
-    pin::Pin,
     sync::Arc,
+    time::SystemTime,
 };
 
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use object_rainbow::{
     Address, ExtraFor, FullHash, Hash, Inline, InlineOutput, ListHashes, MaybeHasNiche, Object,
     OptionalHash, Parse, ParseInline, ParseSlice, ParseSliceExtra, PointInput, PointVisitor,
@@ -12,7 +13,31 @@ use object_rainbow::{
 };
 use object_rainbow_point::{ExtractResolve, Extras, Point};
 
+use self::saving::{Children, Saving};
+
+pub use self::{
+    gc::{Gc, GcReport},
+    index::ObjectIndex,
+    memory::MemoryStore,
+    reflog::{ReflogEntry, ReflogStore},
+    scrub::{Damage, Damaged, Scrub, ScrubReport},
+    sync::{StoreSync, SyncProgress, sync},
+    tiered::{TieredStore, WritePolicy},
+    transaction::{RefTransaction, RefUpdate},
+    watch::{POLL_INTERVAL, poll_ref},
+};
+
 mod externally_stored;
+mod gc;
+mod index;
+mod memory;
+mod reflog;
+mod saving;
+mod scrub;
+mod sync;
+mod tiered;
+mod transaction;
+mod watch;
 
 pub trait RainbowFuture: Send + Future<Output = object_rainbow::Result<Self::T>> {
     type T;
@@ -22,21 +47,6 @@ impl<F: Send + Future<Output = object_rainbow::Result<T>>, T> RainbowFuture for
     type T = T;
 }
 
-struct StoreVisitor<'a, 'x, S: ?Sized> {
-    store: &'a S,
-    futures: &'x mut Vec<Pin<Box<dyn 'a + Send + Future<Output = object_rainbow::Result<()>>>>>,
-}
-
-impl<'a, 'x, S: RainbowStore> PointVisitor for StoreVisitor<'a, 'x, S> {
-    fn visit(&mut self, point: &(impl 'static + SingularFetch<T: Traversible> + Clone)) {
-        let point = point.clone();
-        let store = self.store;
-        self.futures.push(Box::pin(async move {
-            store.save_point(&point).await.map(|_| ())
-        }));
-    }
-}
-
 struct StoreResolve<S> {
     store: S,
 }
@@ -84,16 +94,11 @@ pub trait RainbowStore: 'static + Send + Sync + Clone + PartialEq {
             Ok(())
         }
     }
+    /// Save everything `object` references that isn't stored yet, keeping at most
+    /// [`Self::concurrency`] requests in flight.
     fn save_topology(&self, object: &impl Topological) -> impl RainbowFuture<T = ()> {
-        let mut futures = Vec::with_capacity(object.point_count());
-        object.traverse(&mut StoreVisitor {
-            store: self,
-            futures: &mut futures,
-        });
-        async {
-            futures_util::future::try_join_all(futures).await?;
-            Ok(())
-        }
+        let children = Children::of(self, object);
+        async move { Saving::new(self, self.concurrency()).save(children).await }
     }
     fn save_object(&self, object: &impl Traversible) -> impl RainbowFuture<T = ()> {
         async {
@@ -124,6 +129,43 @@ pub trait RainbowStore: 'static + Send + Sync + Clone + PartialEq {
     fn contains(&self, hash: Hash) -> impl RainbowFuture<T = bool>;
     fn fetch(&self, hash: Hash)
     -> impl RainbowFuture<T = impl 'static + Send + Sync + AsRef<[u8]>>;
+    /// Hashes of all stored objects, along with when each was written if the store knows that.
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        futures_util::stream::once(async { Err(object_rainbow::Error::Unimplemented) })
+    }
+    /// Remove objects, regardless of whether anything still refers to them. Hashes that aren't
+    /// stored are ignored.
+    fn delete(&self, hashes: &[Hash]) -> impl RainbowFuture<T = ()> {
+        let _ = hashes;
+        async { Err(object_rainbow::Error::Unimplemented) }
+    }
+    /// Store `data` under `hash` as is, for copying what another store's [`Self::fetch`] returned.
+    /// Nothing checks that they match. Stores keeping more than the data itself can't do this.
+    fn save_raw(&self, hash: Hash, data: &[u8]) -> impl RainbowFuture<T = ()> {
+        let _ = (hash, data);
+        async { Err(object_rainbow::Error::Unimplemented) }
+    }
+    /// How many requests saving a tree keeps in flight at once, see [`Self::save_topology`].
+    fn concurrency(&self) -> usize {
+        16
+    }
+    /// [`Self::contains`] for each of `hashes`, in the same order.
+    fn contains_many(&self, hashes: &[Hash]) -> impl RainbowFuture<T = Vec<bool>> {
+        futures_util::stream::iter(hashes.iter().map(|hash| self.contains(*hash)))
+            .buffered(self.concurrency())
+            .try_collect()
+    }
+    /// [`Self::save_data`] for each of `objects`.
+    fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> impl RainbowFuture<T = ()> {
+        futures_util::stream::iter(objects.into_iter().map(|wh| self.save_data(wh)))
+            .buffer_unordered(self.concurrency())
+            .try_collect()
+    }
 }
 
 pub trait RainbowStoreMut: RainbowStore {
@@ -142,6 +184,86 @@ pub trait RainbowStoreMut: RainbowStore {
     ) -> impl RainbowFuture<T = ()>;
     fn fetch_ref(&self, key: &str) -> impl RainbowFuture<T = OptionalHash>;
     fn ref_exists(&self, key: &str) -> impl RainbowFuture<T = bool>;
+    /// Refs whose keys start with `prefix`, along with what they point to.
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        let _ = prefix;
+        futures_util::stream::once(async { Err(object_rainbow::Error::Unimplemented) })
+    }
+    /// Remove `key` if it's still at `old`, or regardless when `old` is `None`. Removing a ref
+    /// that doesn't exist isn't an error.
+    fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> impl RainbowFuture<T = ()> {
+        let _ = (key, old);
+        async { Err(object_rainbow::Error::Unimplemented) }
+    }
+    /// Move `from` to `to`, which must not exist yet.
+    ///
+    /// By default, creates `to` and then deletes `from`, so both briefly exist at once.
+    fn rename_ref(&self, from: &str, to: &str) -> impl RainbowFuture<T = ()> {
+        async move {
+            let hash = self
+                .fetch_ref(from)
+                .await?
+                .get()
+                .ok_or(object_rainbow::Error::HashNotFound)?;
+            self.update_ref(to, Some(OptionalHash::NONE), hash).await?;
+            self.delete_ref(from, Some(hash.into())).await
+        }
+    }
+    /// Apply all `updates`, or none of them if any `old` doesn't match, in which case the error
+    /// names every conflicting key.
+    ///
+    /// By default, checks all refs and then calls [`Self::update_ref`] for each, so this is not
+    /// atomic: a concurrent writer can make a later update fail after earlier ones are applied.
+    fn update_refs(&self, updates: Vec<RefUpdate>) -> impl RainbowFuture<T = ()> {
+        async move {
+            let mut conflicts = Vec::new();
+            for RefUpdate { key, old, .. } in &updates {
+                if let Some(old) = *old
+                    && self.fetch_ref(key).await? != old
+                {
+                    conflicts.push(key);
+                }
+            }
+            if !conflicts.is_empty() {
+                return Err(RefUpdate::conflict_error(&conflicts));
+            }
+            for RefUpdate { key, old, hash } in &updates {
+                self.update_ref(key, *old, *hash).await?;
+            }
+            Ok(())
+        }
+    }
+    /// The value of `key`, then every value it changes to. Changes in quick succession may be
+    /// reported only once.
+    ///
+    /// By default, polls every [`POLL_INTERVAL`], see [`poll_ref`].
+    fn watch(&self, key: &str) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        poll_ref(self, key, POLL_INTERVAL)
+    }
+    /// Recorded transitions of `key`, oldest first. Only [`ReflogStore`] keeps them, other stores
+    /// return none.
+    fn reflog(&self, key: &str) -> impl RainbowFuture<T = Vec<ReflogEntry>> {
+        let _ = key;
+        async { Ok(Vec::new()) }
+    }
+    /// Keys with a [`Self::reflog`], including those of refs that have since been deleted. Only
+    /// [`ReflogStore`] keeps them, other stores list none.
+    fn list_reflogs(&self) -> impl Send + Stream<Item = object_rainbow::Result<String>> {
+        futures_util::stream::empty()
+    }
+    /// Set `key` back to what `entry` changed it to.
+    fn restore_ref(&self, key: &str, entry: &ReflogEntry) -> impl RainbowFuture<T = ()> {
+        let hash = entry.new.get();
+        async move {
+            match hash {
+                Some(hash) => self.update_ref(key, None, hash).await,
+                None => self.delete_ref(key, None).await,
+            }
+        }
+    }
     fn store_ref_raw<
         T: Object<Extra>,
         K: Send + Sync + AsRef<str>,
@@ -181,6 +303,11 @@ impl<S, Extra> StoreMut<S, Extra> {
 }
 
 impl<S: RainbowStoreMut, Extra: 'static + Send + Sync + Clone> StoreMut<S, Extra> {
+    /// Start a [`RefTransaction`] on the underlying store.
+    pub fn transaction(&self) -> RefTransaction<S> {
+        RefTransaction::new(self.store.clone())
+    }
+
     pub async fn exists<K: Send + Sync + AsRef<str>>(
         &self,
         key: K,
@@ -248,6 +375,33 @@ impl<S: RainbowStoreMut, Extra: 'static + Send + Sync + Clone> StoreMut<S, Extra
         }
     }
 
+    pub async fn reflog<K: Send + Sync + AsRef<str>>(
+        &self,
+        key: K,
+    ) -> object_rainbow::Result<Vec<ReflogEntry>> {
+        self.store.reflog(key.as_ref()).await
+    }
+
+    pub async fn restore<K: Send + Sync + AsRef<str>>(
+        &self,
+        key: K,
+        entry: &ReflogEntry,
+    ) -> object_rainbow::Result<()> {
+        self.store.restore_ref(key.as_ref(), entry).await
+    }
+
+    /// [`Point`]s that `key` refers to, following [`RainbowStoreMut::watch`]. `None` while the
+    /// ref doesn't exist.
+    pub fn watch<T: Object<Extra>>(
+        &self,
+        key: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<Option<Point<T>>>> {
+        self.store.watch(key).map_ok(|hash| {
+            hash.get()
+                .map(|hash| self.store.point_extra(hash, self.extra.clone()))
+        })
+    }
+
     pub async fn reference<T: Object<Extra>, K: Send + Sync + AsRef<str>>(
         &self,
         key: K,
@@ -457,4 +611,13 @@ pub trait ExternalStore: 'static + Send + Sync + Clone + PartialEq {
     fn load<T: ParseSlice + Tagged>(&self, id: &Self::Id) -> impl RainbowFuture<T = T> {
         externally_stored::load::<_, T>(self, id)
     }
+    /// Check everything reachable from the `T` stored under `id`: that headers parse and carry
+    /// the tags of the types pointing to them, that their topologies match the objects, and that
+    /// objects hash to what they're referred to by. Nothing gets repaired.
+    fn scrub<T: ParseSlice + Traversible>(
+        &self,
+        id: &Self::Id,
+    ) -> impl RainbowFuture<T = ScrubReport<Self::Id>> {
+        externally_stored::scrub::<_, T>(self, id)
+    }
 }
@@ -0,0 +1,214 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use event_listener::Event;
+use futures_util::Stream;
+use object_rainbow::{Hash, OptionalHash, ToOutput, WithHash};
+
+use crate::{RainbowStore, RainbowStoreMut, RefUpdate};
+
+#[derive(Debug, Default)]
+struct State {
+    objects: HashMap<Hash, (Arc<[u8]>, SystemTime)>,
+    refs: BTreeMap<String, Hash>,
+}
+
+impl State {
+    fn fetch_ref(&self, key: &str) -> OptionalHash {
+        self.refs
+            .get(key)
+            .map_or(OptionalHash::NONE, |hash| (*hash).into())
+    }
+
+    fn check_ref(&self, key: &str, old: Option<OptionalHash>) -> bool {
+        old.is_none_or(|old| self.fetch_ref(key) == old)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: Mutex<State>,
+    changed: Event,
+}
+
+/// [`RainbowStoreMut`] keeping everything in memory, for tests and short-lived caches.
+///
+/// All ref operations are atomic, and [`RainbowStoreMut::watch`] is woken up by changes instead
+/// of polling.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<Inner>,
+}
+
+impl PartialEq for MemoryStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&State) -> T) -> T {
+        f(&self.inner.state.lock().unwrap())
+    }
+
+    /// Change refs through `f`, waking up watchers afterwards.
+    fn modify<T>(
+        &self,
+        f: impl FnOnce(&mut State) -> object_rainbow::Result<T>,
+    ) -> object_rainbow::Result<T> {
+        let result = f(&mut self.inner.state.lock().unwrap());
+        self.inner.changed.notify(usize::MAX);
+        result
+    }
+}
+
+impl RainbowStore for MemoryStore {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        self.save_raw(wh.data_hash(), &wh.data.vec()).await
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        Ok(self.read(|state| state.objects.contains_key(&hash)))
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Arc<[u8]>> {
+        self.read(|state| state.objects.get(&hash).map(|(data, _)| data.clone()))
+            .ok_or(object_rainbow::Error::HashNotFound)
+    }
+
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        let objects = self.read(|state| {
+            state
+                .objects
+                .iter()
+                .map(|(hash, (_, written))| Ok((*hash, Some(*written))))
+                .collect::<Vec<_>>()
+        });
+        futures_util::stream::iter(objects)
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        let objects = &mut self.inner.state.lock().unwrap().objects;
+        for hash in hashes {
+            objects.remove(hash);
+        }
+        Ok(())
+    }
+
+    async fn save_raw(&self, hash: Hash, data: &[u8]) -> object_rainbow::Result<()> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .objects
+            .entry(hash)
+            .or_insert_with(|| (data.into(), SystemTime::now()));
+        Ok(())
+    }
+}
+
+impl RainbowStoreMut for MemoryStore {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.update_refs(vec![RefUpdate {
+            key: key.to_owned(),
+            old,
+            hash,
+        }])
+        .await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        Ok(self.read(|state| state.fetch_ref(key)))
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        Ok(self.read(|state| state.refs.contains_key(key)))
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        let refs = self.read(|state| {
+            state
+                .refs
+                .range(prefix.to_owned()..)
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .map(|(key, hash)| Ok((key.clone(), *hash)))
+                .collect::<Vec<_>>()
+        });
+        futures_util::stream::iter(refs)
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        self.modify(|state| {
+            if !state.check_ref(key, old) {
+                return Err(RefUpdate::changed(key));
+            }
+            state.refs.remove(key);
+            Ok(())
+        })
+    }
+
+    async fn rename_ref(&self, from: &str, to: &str) -> object_rainbow::Result<()> {
+        self.modify(|state| {
+            if state.refs.contains_key(to) {
+                return Err(RefUpdate::changed(to));
+            }
+            let hash = state
+                .refs
+                .remove(from)
+                .ok_or(object_rainbow::Error::HashNotFound)?;
+            state.refs.insert(to.to_owned(), hash);
+            Ok(())
+        })
+    }
+
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        self.modify(|state| {
+            let conflicts = updates
+                .iter()
+                .filter(|RefUpdate { key, old, .. }| !state.check_ref(key, *old))
+                .map(|RefUpdate { key, .. }| key)
+                .collect::<Vec<_>>();
+            if !conflicts.is_empty() {
+                return Err(RefUpdate::conflict_error(&conflicts));
+            }
+            for RefUpdate { key, hash, .. } in updates {
+                state.refs.insert(key, hash);
+            }
+            Ok(())
+        })
+    }
+
+    fn watch(&self, key: &str) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        futures_util::stream::unfold(None, move |last| async move {
+            loop {
+                let changed = self.inner.changed.listen();
+                let hash = self.read(|state| state.fetch_ref(key));
+                if last != Some(hash) {
+                    return Some((Ok(hash), Some(hash)));
+                }
+                changed.await;
+            }
+        })
+    }
+}
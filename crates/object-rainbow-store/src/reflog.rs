@@ -0,0 +1,344 @@
+use std::{
+    collections::HashSet,
+    pin::pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_io::Timer;
+use futures_util::{Stream, TryStreamExt};
+use genawaiter_try_stream::try_stream;
+use object_rainbow::{Hash, OptionalHash, ParseSliceRefless, ToOutput, WithHash, numeric::Le};
+
+use crate::{RainbowFuture, RainbowStore, RainbowStoreMut, RefUpdate};
+
+/// One transition of a ref, as recorded by [`ReflogStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub old: OptionalHash,
+    pub new: OptionalHash,
+}
+
+type RawEntry = (Le<u64>, OptionalHash, OptionalHash);
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// How many times an operation conflicting with concurrent writers is tried, see [`retry`].
+const ATTEMPTS: u32 = 8;
+
+/// Wait before trying again after `error`, longer after each attempt. Returns `error` if it isn't
+/// a [`RefUpdate::is_conflict`] or if there have been [`ATTEMPTS`] already.
+async fn retry(attempt: &mut u32, error: object_rainbow::Error) -> object_rainbow::Result<()> {
+    *attempt += 1;
+    if !RefUpdate::is_conflict(&error) || *attempt >= ATTEMPTS {
+        return Err(error);
+    }
+    Timer::after(Duration::from_millis(1 << *attempt)).await;
+    Ok(())
+}
+
+/// [`RainbowStoreMut`] adapter recording every transition of every ref, see
+/// [`RainbowStoreMut::reflog`].
+///
+/// Refs are kept in `S` under `refs/<key>`. The log of each is saved as an object pointed to by
+/// `logs/<key>`, which is replaced in the same [`RainbowStoreMut::update_refs`] call as the ref
+/// itself, so updates are logged as atomically as `S` allows. Deletions are logged right after
+/// they happen.
+///
+/// By default, the last 100 entries of each ref are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflogStore<S> {
+    store: S,
+    max_entries: usize,
+    max_age: Option<Duration>,
+}
+
+impl<S> ReflogStore<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            max_entries: 100,
+            max_age: None,
+        }
+    }
+
+    /// Keep at most `max_entries` entries per ref.
+    pub fn with_max_entries(self, max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            ..self
+        }
+    }
+
+    /// Drop entries older than `max_age` whenever a ref's log is written.
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+}
+
+impl<S: RainbowStoreMut> ReflogStore<S> {
+    fn ref_key(key: &str) -> String {
+        format!("refs/{key}")
+    }
+
+    fn log_key(key: &str) -> String {
+        format!("logs/{key}")
+    }
+
+    async fn load_log(
+        &self,
+        key: &str,
+    ) -> object_rainbow::Result<(OptionalHash, Vec<ReflogEntry>)> {
+        let log = self.store.fetch_ref(&Self::log_key(key)).await?;
+        let Some(hash) = log.get() else {
+            return Ok((log, Vec::new()));
+        };
+        let entries = Vec::<RawEntry>::parse_slice_refless(self.store.fetch(hash).await?.as_ref())?
+            .into_iter()
+            .map(|(Le(timestamp), old, new)| ReflogEntry {
+                timestamp,
+                old,
+                new,
+            })
+            .collect();
+        Ok((log, entries))
+    }
+
+    /// Append an `old → new` entry, drop what's out of bounds, and save the log.
+    async fn save_log(
+        &self,
+        mut entries: Vec<ReflogEntry>,
+        old: OptionalHash,
+        new: OptionalHash,
+    ) -> object_rainbow::Result<Hash> {
+        let timestamp = now();
+        entries.push(ReflogEntry {
+            timestamp,
+            old,
+            new,
+        });
+        if let Some(max_age) = self.max_age {
+            let oldest = timestamp.saturating_sub(max_age.as_millis() as u64);
+            entries.retain(|entry| entry.timestamp >= oldest);
+        }
+        entries.drain(..entries.len().saturating_sub(self.max_entries));
+        let raw = entries
+            .iter()
+            .map(|entry| (Le(entry.timestamp), entry.old, entry.new))
+            .collect::<Vec<RawEntry>>();
+        let wh = WithHash {
+            diff: Hash::default(),
+            data: &raw,
+        };
+        let hash = wh.data_hash();
+        self.store.save_data(wh).await?;
+        Ok(hash)
+    }
+}
+
+impl<S: RainbowStoreMut> RainbowStore for ReflogStore<S> {
+    fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> impl RainbowFuture<T = ()> {
+        self.store.save_data(wh)
+    }
+
+    fn contains(&self, hash: Hash) -> impl RainbowFuture<T = bool> {
+        self.store.contains(hash)
+    }
+
+    fn fetch(
+        &self,
+        hash: Hash,
+    ) -> impl RainbowFuture<T = impl 'static + Send + Sync + AsRef<[u8]>> {
+        self.store.fetch(hash)
+    }
+
+    /// Objects of `S`, except for current logs, so that GC on this store doesn't sweep them.
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        try_stream(async move |co| {
+            let logs = self
+                .store
+                .list_refs("logs/")
+                .map_ok(|(_, hash)| hash)
+                .try_collect::<HashSet<_>>()
+                .await?;
+            let mut objects = pin!(self.store.list_objects());
+            while let Some((hash, written)) = objects.try_next().await? {
+                if !logs.contains(&hash) {
+                    co.yield_((hash, written)).await;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn delete(&self, hashes: &[Hash]) -> impl RainbowFuture<T = ()> {
+        self.store.delete(hashes)
+    }
+
+    fn save_raw(&self, hash: Hash, data: &[u8]) -> impl RainbowFuture<T = ()> {
+        self.store.save_raw(hash, data)
+    }
+
+    fn concurrency(&self) -> usize {
+        self.store.concurrency()
+    }
+
+    fn contains_many(&self, hashes: &[Hash]) -> impl RainbowFuture<T = Vec<bool>> {
+        self.store.contains_many(hashes)
+    }
+
+    fn save_many(
+        &self,
+        objects: Vec<WithHash<'_, impl Send + Sync + ToOutput>>,
+    ) -> impl RainbowFuture<T = ()> {
+        self.store.save_many(objects)
+    }
+}
+
+impl<S: RainbowStoreMut> RainbowStoreMut for ReflogStore<S> {
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.update_refs(vec![RefUpdate {
+            key: key.to_owned(),
+            old,
+            hash,
+        }])
+        .await
+    }
+
+    async fn fetch_ref(&self, key: &str) -> object_rainbow::Result<OptionalHash> {
+        self.store.fetch_ref(&Self::ref_key(key)).await
+    }
+
+    async fn ref_exists(&self, key: &str) -> object_rainbow::Result<bool> {
+        self.store.ref_exists(&Self::ref_key(key)).await
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        try_stream(async move |co| {
+            let prefix = Self::ref_key(prefix);
+            let mut refs = pin!(self.store.list_refs(&prefix));
+            while let Some((key, hash)) = refs.try_next().await? {
+                if let Some(key) = key.strip_prefix("refs/") {
+                    co.yield_((key.to_owned(), hash)).await;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> object_rainbow::Result<()> {
+        let ref_key = Self::ref_key(key);
+        let mut attempt = 0;
+        let current = loop {
+            let current = self.store.fetch_ref(&ref_key).await?;
+            if old.is_some_and(|old| old != current) {
+                return Err(RefUpdate::changed(key));
+            }
+            if current.is_none() {
+                return Ok(());
+            }
+            match self.store.delete_ref(&ref_key, Some(current)).await {
+                Ok(()) => break current,
+                Err(e) => retry(&mut attempt, e).await?,
+            }
+        };
+        let log_key = Self::log_key(key);
+        let mut attempt = 0;
+        loop {
+            let (log, entries) = self.load_log(key).await?;
+            let hash = self.save_log(entries, current, OptionalHash::NONE).await?;
+            match self.store.update_ref(&log_key, Some(log), hash).await {
+                Ok(()) => return Ok(()),
+                Err(e) => retry(&mut attempt, e).await?,
+            }
+        }
+    }
+
+    /// Checks and logs all updates, then applies them along with their logs. If `S` reports a
+    /// conflict that isn't covered by `old`s, such as a concurrent write to a log, retries a few
+    /// times.
+    ///
+    /// Each log is updated before its ref, so if `S` applies only some of the updates, the log may
+    /// get an entry for a transition that didn't happen, but a ref never changes without an entry.
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        let mut attempt = 0;
+        loop {
+            let mut logged = Vec::with_capacity(updates.len() * 2);
+            let mut conflicts = Vec::new();
+            for RefUpdate { key, old, hash } in &updates {
+                let ref_key = Self::ref_key(key);
+                let current = self.store.fetch_ref(&ref_key).await?;
+                if old.is_some_and(|old| old != current) {
+                    conflicts.push(key);
+                    continue;
+                }
+                let (log, entries) = self.load_log(key).await?;
+                logged.push(RefUpdate {
+                    key: Self::log_key(key),
+                    old: Some(log),
+                    hash: self.save_log(entries, current, (*hash).into()).await?,
+                });
+                logged.push(RefUpdate {
+                    key: ref_key,
+                    old: Some(current),
+                    hash: *hash,
+                });
+            }
+            if !conflicts.is_empty() {
+                return Err(RefUpdate::conflict_error(&conflicts));
+            }
+            match self.store.update_refs(logged).await {
+                Ok(()) => return Ok(()),
+                Err(e) => retry(&mut attempt, e).await?,
+            }
+        }
+    }
+
+    fn watch(&self, key: &str) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        try_stream(async move |co| {
+            let key = Self::ref_key(key);
+            let mut hashes = pin!(self.store.watch(&key));
+            while let Some(hash) = hashes.try_next().await? {
+                co.yield_(hash).await;
+            }
+            Ok(())
+        })
+    }
+
+    async fn reflog(&self, key: &str) -> object_rainbow::Result<Vec<ReflogEntry>> {
+        Ok(self.load_log(key).await?.1)
+    }
+
+    fn list_reflogs(&self) -> impl Send + Stream<Item = object_rainbow::Result<String>> {
+        try_stream(async move |co| {
+            let mut logs = pin!(self.store.list_refs("logs/"));
+            while let Some((key, _)) = logs.try_next().await? {
+                if let Some(key) = key.strip_prefix("logs/") {
+                    co.yield_(key.to_owned()).await;
+                }
+            }
+            Ok(())
+        })
+    }
+}
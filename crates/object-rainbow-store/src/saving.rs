@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use async_lock::{Semaphore, SemaphoreGuard};
+use event_listener::Event;
+use object_rainbow::{
+    FullHash, Hash, PointVisitor, SingularFetch, ToOutput, Topological, Traversible, WithHash,
+};
+use object_rainbow_point::ExtractResolve;
+
+use crate::{RainbowStore, StoreResolve, SyncProgress};
+
+type Fetching = Pin<Box<dyn Send + Future<Output = object_rainbow::Result<Fetched>>>>;
+
+/// What saving needs from a fetched object: its data, and points to descend into.
+struct Fetched {
+    diff: Hash,
+    data: Vec<u8>,
+    children: Vec<Child>,
+}
+
+/// A point to save, fetched only if the store turns out to be missing it.
+pub(crate) struct Child {
+    hash: Hash,
+    fetch: Fetching,
+}
+
+impl Child {
+    /// `store` is where the point's own points are going to be saved to.
+    pub(crate) fn new(
+        store: &impl RainbowStore,
+        point: impl 'static + SingularFetch<T: Traversible>,
+    ) -> Self {
+        let store = store.clone();
+        Self {
+            hash: point.hash(),
+            fetch: Box::pin(async move {
+                let object = point.fetch().await?;
+                Ok(Fetched {
+                    diff: object.with_hash().diff,
+                    data: object.vec(),
+                    children: Children::of(&store, &object),
+                })
+            }),
+        }
+    }
+}
+
+/// Points of an object, except for those already resolved from `store`.
+pub(crate) struct Children<'a, S> {
+    store: &'a S,
+    children: Vec<Child>,
+}
+
+impl<'a, S: RainbowStore> Children<'a, S> {
+    pub(crate) fn of(store: &'a S, object: &impl Topological) -> Vec<Child> {
+        let mut children = Self {
+            store,
+            children: Vec::with_capacity(object.point_count()),
+        };
+        object.traverse(&mut children);
+        children.children
+    }
+}
+
+impl<S: RainbowStore> PointVisitor for Children<'_, S> {
+    fn visit(&mut self, point: &(impl 'static + SingularFetch<T: Traversible> + Clone)) {
+        let already_stored = point
+            .extract_resolve::<StoreResolve<S>>()
+            .is_some_and(|(_, resolve)| resolve.store == *self.store);
+        if !already_stored {
+            self.children.push(Child::new(self.store, point.clone()));
+        }
+    }
+}
+
+/// Outcome of saving a hash that other branches of the walk may be waiting on.
+#[derive(Default)]
+struct Saved {
+    done: Event,
+    saved: OnceLock<bool>,
+}
+
+impl Saved {
+    fn finish(&self, saved: bool) {
+        let _ = self.saved.set(saved);
+        self.done.notify(usize::MAX);
+    }
+
+    async fn wait(&self) -> bool {
+        loop {
+            let done = self.done.listen();
+            if let Some(saved) = self.saved.get() {
+                return *saved;
+            }
+            done.await;
+        }
+    }
+}
+
+/// Exclusive right to save a hash. Dropping it without [`Claim::finish`] fails the waiters.
+struct Claim(Arc<Saved>);
+
+impl Claim {
+    fn finish(self) {
+        self.0.finish(true);
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.0.finish(false);
+    }
+}
+
+/// Saves points into a store, with at most `concurrency` requests to it or to the sources of the
+/// points in flight. Each hash is only checked and saved once, however many paths lead to it.
+///
+/// Batch calls hold a permit per hash in the batch, as their defaults run that many requests at
+/// once.
+///
+/// Everything an object references gets saved before the object itself, so the store never has
+/// an object without its subtree, which is what makes skipping stored subtrees sound.
+pub(crate) struct Saving<'s, S> {
+    store: &'s S,
+    progress: Option<&'s (dyn Send + Sync + Fn(SyncProgress))>,
+    semaphore: Semaphore,
+    counters: Mutex<SyncProgress>,
+    claims: Mutex<HashMap<Hash, Arc<Saved>>>,
+}
+
+impl<'s, S: RainbowStore> Saving<'s, S> {
+    pub(crate) fn new(store: &'s S, concurrency: usize) -> Self {
+        Self {
+            store,
+            progress: None,
+            semaphore: Semaphore::new(concurrency.max(1)),
+            counters: Default::default(),
+            claims: Default::default(),
+        }
+    }
+
+    pub(crate) fn with_progress(
+        self,
+        progress: Option<&'s (dyn Send + Sync + Fn(SyncProgress))>,
+    ) -> Self {
+        Self { progress, ..self }
+    }
+
+    pub(crate) fn counters(&self) -> SyncProgress {
+        *self.counters.lock().unwrap()
+    }
+
+    fn report(&self, f: impl FnOnce(&mut SyncProgress)) {
+        let counters = {
+            let mut counters = self.counters.lock().unwrap();
+            f(&mut counters);
+            *counters
+        };
+        if let Some(progress) = self.progress {
+            progress(counters);
+        }
+    }
+
+    /// Wait for one permit, then take whatever more are free, up to `max`. Never waits while
+    /// holding a permit, so concurrent batches can't starve each other.
+    async fn permits(&self, max: usize) -> Vec<SemaphoreGuard<'_>> {
+        let mut permits = vec![self.semaphore.acquire().await];
+        while permits.len() < max
+            && let Some(permit) = self.semaphore.try_acquire()
+        {
+            permits.push(permit);
+        }
+        permits
+    }
+
+    /// Claim the hashes nobody else is saving yet. Returns the claimed ones, and what's left to
+    /// wait for.
+    fn claim(&self, children: Vec<Child>) -> (Vec<(Child, Claim)>, Vec<Arc<Saved>>) {
+        let mut claims = self.claims.lock().unwrap();
+        let mut claimed = Vec::new();
+        let mut waiting = Vec::new();
+        for child in children {
+            match claims.entry(child.hash) {
+                Entry::Occupied(entry) => waiting.push(entry.get().clone()),
+                Entry::Vacant(entry) => {
+                    let saved = entry.insert(Default::default()).clone();
+                    claimed.push((child, Claim(saved)));
+                }
+            }
+        }
+        (claimed, waiting)
+    }
+
+    /// Save whatever of `children` the store is missing, checking them with
+    /// [`RainbowStore::contains_many`] and saving the ones without points of their own with
+    /// [`RainbowStore::save_many`], in batches as large as the free permits allow.
+    pub(crate) fn save(
+        &self,
+        children: Vec<Child>,
+    ) -> Pin<Box<dyn '_ + Send + Future<Output = object_rainbow::Result<()>>>> {
+        Box::pin(async move {
+            let (claimed, waiting) = self.claim(children);
+            let mut missing = Vec::new();
+            if !claimed.is_empty() {
+                let hashes = claimed
+                    .iter()
+                    .map(|(child, _)| child.hash)
+                    .collect::<Vec<_>>();
+                let mut contains = Vec::with_capacity(hashes.len());
+                let mut rest = &hashes[..];
+                while !rest.is_empty() {
+                    let permits = self.permits(rest.len()).await;
+                    let (batch, next) = rest.split_at(permits.len());
+                    contains.extend(self.store.contains_many(batch).await?);
+                    rest = next;
+                }
+                for ((child, claim), contains) in claimed.into_iter().zip(contains) {
+                    self.report(|counters| {
+                        counters.checked += 1;
+                        counters.skipped += contains as usize;
+                    });
+                    if contains {
+                        claim.finish();
+                    } else {
+                        missing.push((child, claim));
+                    }
+                }
+            }
+            let fetched = futures_util::future::try_join_all(missing.into_iter().map(
+                async |(child, claim)| {
+                    let _permit = self.semaphore.acquire().await;
+                    Ok::<_, object_rainbow::Error>((child.fetch.await?, claim))
+                },
+            ))
+            .await?;
+            let (leaves, nodes): (Vec<_>, Vec<_>) = fetched
+                .into_iter()
+                .partition(|(fetched, _)| fetched.children.is_empty());
+            let leaves = async {
+                if leaves.is_empty() {
+                    return Ok(());
+                }
+                // only the data, as pending fetches of children can't be shared across awaits
+                let leaves = leaves
+                    .into_iter()
+                    .map(|(fetched, claim)| (fetched.diff, fetched.data, claim))
+                    .collect::<Vec<_>>();
+                let mut rest = &leaves[..];
+                while !rest.is_empty() {
+                    let permits = self.permits(rest.len()).await;
+                    let (batch, next) = rest.split_at(permits.len());
+                    self.store
+                        .save_many(
+                            batch
+                                .iter()
+                                .map(|(diff, data, _)| WithHash { diff: *diff, data })
+                                .collect(),
+                        )
+                        .await?;
+                    drop(permits);
+                    self.report(|counters| counters.copied += batch.len());
+                    rest = next;
+                }
+                for (_, _, claim) in leaves {
+                    claim.finish();
+                }
+                Ok(())
+            };
+            let nodes = futures_util::future::try_join_all(nodes.into_iter().map(
+                async |(fetched, claim)| {
+                    self.save(fetched.children).await?;
+                    {
+                        let _permit = self.semaphore.acquire().await;
+                        self.store
+                            .save_data(WithHash {
+                                diff: fetched.diff,
+                                data: &fetched.data,
+                            })
+                            .await?;
+                    }
+                    self.report(|counters| counters.copied += 1);
+                    claim.finish();
+                    Ok::<_, object_rainbow::Error>(())
+                },
+            ));
+            futures_util::future::try_join(leaves, nodes).await?;
+            for saved in waiting {
+                if !saved.wait().await {
+                    return Err(object_rainbow::error_operation!(
+                        "failed to save an object saved concurrently"
+                    ));
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::{HashMap, HashSet},
+    pin::pin,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::TryStreamExt;
+use object_rainbow::{Hash, Object, SingularFetch, ToOutput, Traversible, WithHash};
+
+use crate::{
+    RainbowFuture, RainbowStore, RainbowStoreMut,
+    gc::{Node, Roots, Walk},
+};
+
+/// What's wrong with an object, as found by [`Scrub`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// Its data doesn't parse, or doesn't hash to what it's stored under.
+    Corrupt,
+    /// Its data ends early.
+    Truncated,
+    /// It's referred to, but isn't stored.
+    Missing,
+}
+
+impl Damage {
+    /// `None` for errors that don't say anything about the object itself, like I/O failures.
+    pub fn of(e: &object_rainbow::Error) -> Option<Self> {
+        use object_rainbow::Error;
+        use std::io::ErrorKind;
+        match e {
+            Error::HashNotFound => Some(Self::Missing),
+            Error::EndOfInput => Some(Self::Truncated),
+            Error::Parse(_)
+            | Error::Consistency(_)
+            | Error::ExtraInputLeft
+            | Error::AddressOutOfBounds
+            | Error::ResolutionMismatch
+            | Error::FullHashMismatch
+            | Error::DiscriminantOverflow
+            | Error::Zero
+            | Error::OutOfBounds
+            | Error::UnsupportedLength => Some(Self::Corrupt),
+            Error::Io(e) => match e.kind() {
+                ErrorKind::NotFound => Some(Self::Missing),
+                ErrorKind::UnexpectedEof => Some(Self::Truncated),
+                ErrorKind::InvalidData => Some(Self::Corrupt),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// One damaged object in a [`ScrubReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Damaged<Id = Hash> {
+    pub id: Id,
+    pub damage: Damage,
+    /// The object referring to this one, if it was reached by walking from a root.
+    pub referrer: Option<Id>,
+    /// Whether it has been replaced with a copy from the replica.
+    pub repaired: bool,
+}
+
+/// Outcome of a [`Scrub`] run, or of [`ExternalStore::scrub`](crate::ExternalStore::scrub).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrubReport<Id = Hash> {
+    /// Objects listed and read back.
+    pub listed: usize,
+    /// Objects reached from roots that parsed and hashed as their type.
+    pub walked: usize,
+    pub damaged: Vec<Damaged<Id>>,
+}
+
+impl<Id> Default for ScrubReport<Id> {
+    fn default() -> Self {
+        Self {
+            listed: 0,
+            walked: 0,
+            damaged: Vec::new(),
+        }
+    }
+}
+
+impl<Id> ScrubReport<Id> {
+    pub fn is_clean(&self) -> bool {
+        self.damaged.is_empty()
+    }
+
+    pub(crate) fn damaged(
+        &mut self,
+        id: Id,
+        referrer: Option<Id>,
+        e: object_rainbow::Error,
+    ) -> object_rainbow::Result<()> {
+        let Some(damage) = Damage::of(&e) else {
+            return Err(e);
+        };
+        self.damaged.push(Damaged {
+            id,
+            damage,
+            referrer,
+            repaired: false,
+        });
+        Ok(())
+    }
+}
+
+/// `S` as seen by the walk of a [`Scrub`], except for `candidate`, which gets served instead of
+/// what `S` has under its hash, so that a replica's copy can be checked as its type before it's
+/// saved.
+#[derive(Clone)]
+struct Checked<S> {
+    store: S,
+    candidate: Arc<Mutex<Candidate>>,
+}
+
+type Candidate = Option<(Hash, Arc<[u8]>)>;
+
+impl<S: PartialEq> PartialEq for Checked<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.store == other.store && Arc::ptr_eq(&self.candidate, &other.candidate)
+    }
+}
+
+impl<S: RainbowStore> RainbowStore for Checked<S> {
+    fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> impl RainbowFuture<T = ()> {
+        self.store.save_data(wh)
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        if self.candidate(hash).is_some() {
+            return Ok(true);
+        }
+        self.store.contains(hash).await
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Arc<[u8]>> {
+        if let Some(data) = self.candidate(hash) {
+            return Ok(data);
+        }
+        Ok(self.store.fetch(hash).await?.as_ref().into())
+    }
+}
+
+impl<S> Checked<S> {
+    fn candidate(&self, hash: Hash) -> Option<Arc<[u8]>> {
+        self.candidate
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(candidate, _)| *candidate == hash)
+            .map(|(_, data)| data.clone())
+    }
+
+    /// Run `walk` with `data` served for `hash`.
+    async fn check(&self, walk: &Walk, hash: Hash, data: Arc<[u8]>) -> object_rainbow::Result<()> {
+        *self.candidate.lock().unwrap() = Some((hash, data));
+        let result = walk().await;
+        *self.candidate.lock().unwrap() = None;
+        result.map(drop)
+    }
+}
+
+/// Integrity check for a [`RainbowStoreMut`].
+///
+/// Every listed object is read back, which catches what the store itself can notice: stores that
+/// keep the diff along with the data, like `FsStore`, recompute the hash on every read. Beyond that,
+/// objects can only be checked through their types, so, like with [`Gc`](crate::Gc), refs can be
+/// declared with [`Scrub::refs`] and roots added with [`Scrub::root`]. Everything reachable from
+/// those gets parsed and hashed, and references to objects that aren't stored are reported as
+/// [`Damage::Missing`]. Refs with no type declared are skipped.
+///
+/// With [`Scrub::with_replica`], damaged objects reached from [`Scrub::refs`] are replaced with
+/// copies from the replica through [`RainbowStore::save_raw`]. Each copy is first parsed and
+/// hashed as its type, and the object only counts as repaired once it reads back as such from
+/// the store. Others can't be checked, so they're left as they are. What the copies refer to only
+/// gets checked by the next run.
+pub struct Scrub<S, R = S> {
+    store: S,
+    checked: Checked<S>,
+    roots: Roots,
+    replica: Option<R>,
+}
+
+impl<S: RainbowStoreMut> Scrub<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            checked: Checked {
+                store: store.clone(),
+                candidate: Default::default(),
+            },
+            store,
+            roots: Roots::default(),
+            replica: None,
+        }
+    }
+}
+
+impl<S: RainbowStoreMut, R: RainbowStore> Scrub<S, R> {
+    /// Repair from `replica`.
+    pub fn with_replica<Replica: RainbowStore>(self, replica: Replica) -> Scrub<S, Replica> {
+        Scrub {
+            store: self.store,
+            checked: self.checked,
+            roots: self.roots,
+            replica: Some(replica),
+        }
+    }
+
+    /// Refs starting with `prefix` point to `T`s. The longest matching prefix wins.
+    pub fn refs<T: Object>(self, prefix: &str) -> Self {
+        self.refs_extra::<T, ()>(prefix, ())
+    }
+
+    pub fn refs_extra<T: Object<Extra>, Extra: 'static + Send + Sync + Clone>(
+        mut self,
+        prefix: &str,
+        extra: Extra,
+    ) -> Self {
+        self.roots.refs::<T, Extra>(&self.checked, prefix, extra);
+        self
+    }
+
+    /// Also check everything reachable from `point`.
+    pub fn root(mut self, point: impl 'static + SingularFetch<T: Traversible> + Clone) -> Self {
+        self.roots.root(point);
+        self
+    }
+
+    pub async fn run(self) -> object_rainbow::Result<ScrubReport> {
+        let mut report = ScrubReport::default();
+        let store = &self.store;
+        let mut read = pin!(
+            store
+                .list_objects()
+                .map_ok(|(hash, _)| async move { Ok((hash, store.fetch(hash).await.err())) })
+                .try_buffer_unordered(store.concurrency())
+        );
+        while let Some((hash, error)) = read.try_next().await? {
+            report.listed += 1;
+            if let Some(e) = error {
+                report.damaged(hash, None, e)?;
+            }
+        }
+        let mut stack = Vec::new();
+        if !self.roots.is_empty() {
+            for (key, hash) in store.list_refs("").try_collect::<Vec<_>>().await? {
+                stack.extend(self.roots.ref_root(&key, hash));
+            }
+        }
+        let mut stack = stack
+            .into_iter()
+            .chain(self.roots.extra)
+            .map(|(hash, walk)| (hash, walk, None))
+            .collect::<Vec<_>>();
+        let listed = report
+            .damaged
+            .iter()
+            .enumerate()
+            .map(|(i, damaged)| (damaged.id, i))
+            .collect::<HashMap<_, _>>();
+        // how to check damaged objects as their types, for those reached from roots
+        let mut checks = HashMap::new();
+        let mut walked = HashSet::new();
+        while let Some((hash, walk, referrer)) = stack.pop() {
+            if !walked.insert(hash) {
+                continue;
+            }
+            if let Some(&i) = listed.get(&hash) {
+                report.damaged[i].referrer = referrer;
+                checks.insert(hash, walk);
+                continue;
+            }
+            match walk().await {
+                Ok(Node { children, .. }) => {
+                    report.walked += 1;
+                    stack.extend(
+                        children
+                            .into_iter()
+                            .map(|(child, walk)| (child, walk, Some(hash))),
+                    );
+                }
+                Err(e) => {
+                    report.damaged(hash, referrer, e)?;
+                    checks.insert(hash, walk);
+                }
+            }
+        }
+        if let Some(replica) = &self.replica {
+            for damaged in &mut report.damaged {
+                let Some(walk) = checks.get(&damaged.id) else {
+                    continue;
+                };
+                let data = match replica.fetch(damaged.id).await {
+                    Ok(data) => Arc::<[u8]>::from(data.as_ref()),
+                    Err(e) if Damage::of(&e).is_some() => continue,
+                    Err(e) => return Err(e),
+                };
+                match self.checked.check(walk, damaged.id, data.clone()).await {
+                    Ok(()) => {}
+                    Err(e) if Damage::of(&e).is_some() => continue,
+                    Err(e) => return Err(e),
+                }
+                // saving doesn't overwrite what's already stored under the same hash
+                if damaged.damage != Damage::Missing {
+                    store.delete(&[damaged.id]).await?;
+                }
+                store.save_raw(damaged.id, &data).await?;
+                match walk().await {
+                    Ok(_) => damaged.repaired = true,
+                    Err(e) if Damage::of(&e).is_some() => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(report)
+    }
+}
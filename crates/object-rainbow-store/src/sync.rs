@@ -0,0 +1,114 @@
+use object_rainbow::{Hash, Object, OptionalHash};
+
+use crate::{
+    RainbowStore, RainbowStoreMut,
+    saving::{Child, Saving},
+};
+
+/// Counters passed to the [`StoreSync::with_progress`] callback and returned by a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncProgress {
+    /// Objects looked up in the destination.
+    pub checked: usize,
+    /// Objects the destination already had, whose subtrees haven't been walked.
+    pub skipped: usize,
+    /// Objects copied to the destination.
+    pub copied: usize,
+}
+
+type Progress = Box<dyn Send + Sync + Fn(SyncProgress)>;
+
+/// Copies everything reachable from a root from one store to another.
+///
+/// Like with [`Gc`](crate::Gc), the type behind the root hash has to be known to walk it. Objects
+/// the destination already has are assumed to come with everything they reference, so their
+/// subtrees are skipped. Objects reachable through several paths are only checked and copied
+/// once.
+pub struct StoreSync<Src, Dst> {
+    src: Src,
+    dst: Dst,
+    concurrency: Option<usize>,
+    progress: Option<Progress>,
+}
+
+impl<Src: RainbowStore, Dst: RainbowStore> StoreSync<Src, Dst> {
+    /// By default, as many requests are kept in flight as the destination's
+    /// [`RainbowStore::concurrency`].
+    pub fn new(src: Src, dst: Dst) -> Self {
+        Self {
+            src,
+            dst,
+            concurrency: None,
+            progress: None,
+        }
+    }
+
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: Some(concurrency),
+            ..self
+        }
+    }
+
+    /// Call `progress` whenever an object has been checked or copied.
+    pub fn with_progress(self, progress: impl 'static + Send + Sync + Fn(SyncProgress)) -> Self {
+        Self {
+            progress: Some(Box::new(progress)),
+            ..self
+        }
+    }
+
+    pub async fn run<T: Object>(&self, root: Hash) -> object_rainbow::Result<SyncProgress> {
+        self.run_extra::<T, ()>(root, ()).await
+    }
+
+    pub async fn run_extra<T: Object<Extra>, Extra: 'static + Send + Sync + Clone>(
+        &self,
+        root: Hash,
+        extra: Extra,
+    ) -> object_rainbow::Result<SyncProgress> {
+        let saving = Saving::new(
+            &self.dst,
+            self.concurrency.unwrap_or_else(|| self.dst.concurrency()),
+        )
+        .with_progress(self.progress.as_deref());
+        let root = Child::new(&self.dst, self.src.point_extra::<T, Extra>(root, extra));
+        saving.save(vec![root]).await?;
+        Ok(saving.counters())
+    }
+}
+
+impl<Src: RainbowStore, Dst: RainbowStoreMut> StoreSync<Src, Dst> {
+    /// Copy `root`, then set `key` in the destination to it if `key` is still at `old`.
+    pub async fn run_to_ref<T: Object>(
+        &self,
+        root: Hash,
+        key: &str,
+        old: Option<OptionalHash>,
+    ) -> object_rainbow::Result<SyncProgress> {
+        self.run_to_ref_extra::<T, ()>(root, (), key, old).await
+    }
+
+    pub async fn run_to_ref_extra<T: Object<Extra>, Extra: 'static + Send + Sync + Clone>(
+        &self,
+        root: Hash,
+        extra: Extra,
+        key: &str,
+        old: Option<OptionalHash>,
+    ) -> object_rainbow::Result<SyncProgress> {
+        let progress = self.run_extra::<T, Extra>(root, extra).await?;
+        self.dst.update_ref(key, old, root).await?;
+        Ok(progress)
+    }
+}
+
+/// [`StoreSync::run`] with default settings.
+pub async fn sync<T: Object>(
+    src: &impl RainbowStore,
+    dst: &impl RainbowStore,
+    root: Hash,
+) -> object_rainbow::Result<SyncProgress> {
+    StoreSync::new(src.clone(), dst.clone())
+        .run::<T>(root)
+        .await
+}
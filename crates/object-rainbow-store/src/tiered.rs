@@ -0,0 +1,456 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use futures_util::{Stream, TryStreamExt};
+use hex::FromHex;
+use object_rainbow::{Hash, OptionalHash, ToOutput, WithHash};
+
+use crate::{RainbowFuture, RainbowStore, RainbowStoreMut, RefUpdate, ReflogEntry};
+
+/// Where [`TieredStore`] writes objects to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Write to the slow tier, then to the fast one.
+    #[default]
+    Through,
+    /// Write to the fast tier only, and to the slow one on [`TieredStore::flush`].
+    Back,
+}
+
+/// Fast-tier refs marking objects that haven't been flushed yet, named `dirty/<seq>-<hash>` and
+/// pointing to the object's diff. `seq` is the order they were saved in.
+const DIRTY: &str = "dirty/";
+
+fn dirty_key(seq: u64, hash: Hash) -> String {
+    format!("{DIRTY}{seq:016x}-{}", hex::encode(hash))
+}
+
+fn parse_dirty_key(key: &str) -> Option<(u64, Hash)> {
+    let (seq, hash) = key.strip_prefix(DIRTY)?.split_once('-')?;
+    Some((
+        u64::from_str_radix(seq, 16).ok()?,
+        Hash::from_hex(hash).ok()?,
+    ))
+}
+
+#[derive(Debug)]
+struct Entry {
+    size: usize,
+    used: u64,
+    dirty: Option<u64>,
+}
+
+/// Objects of the fast tier that the [`TieredStore`] knows about.
+#[derive(Debug, Default)]
+struct Cache {
+    entries: HashMap<Hash, Entry>,
+    lru: BTreeMap<u64, Hash>,
+    /// Objects missing from the slow tier, with their diffs, by the order they were saved in.
+    dirty: BTreeMap<u64, (Hash, Hash)>,
+    seq: u64,
+    clock: u64,
+    size: usize,
+}
+
+impl Cache {
+    fn touch(&mut self, hash: Hash, size: usize) {
+        self.clock += 1;
+        match self.entries.get_mut(&hash) {
+            Some(entry) => {
+                self.lru.remove(&entry.used);
+                entry.used = self.clock;
+            }
+            None => {
+                self.entries.insert(
+                    hash,
+                    Entry {
+                        size,
+                        used: self.clock,
+                        dirty: None,
+                    },
+                );
+                self.size += size;
+            }
+        }
+        self.lru.insert(self.clock, hash);
+    }
+
+    fn mark(&mut self, seq: u64, hash: Hash, diff: Hash) {
+        if let Some(entry) = self.entries.get_mut(&hash) {
+            entry.dirty = Some(seq);
+        }
+        self.dirty.insert(seq, (hash, diff));
+        self.seq = self.seq.max(seq + 1);
+    }
+
+    fn clean(&mut self, seq: u64) {
+        if let Some((hash, _)) = self.dirty.remove(&seq)
+            && let Some(entry) = self.entries.get_mut(&hash)
+            && entry.dirty == Some(seq)
+        {
+            entry.dirty = None;
+        }
+    }
+
+    /// Untrack `hash`, returning its dirty ref's `seq`, if any.
+    fn remove(&mut self, hash: Hash) -> Option<u64> {
+        let entry = self.entries.remove(&hash)?;
+        self.lru.remove(&entry.used);
+        self.size -= entry.size;
+        let seq = entry.dirty?;
+        self.dirty.remove(&seq);
+        Some(seq)
+    }
+
+    /// Untrack the least recently used objects until within limits. Dirty ones are kept.
+    fn evict(&mut self, max_bytes: Option<usize>, max_objects: Option<usize>) -> Vec<Hash> {
+        let mut size = self.size;
+        let mut count = self.entries.len();
+        let mut evicted = Vec::new();
+        for hash in self.lru.values() {
+            if max_bytes.is_none_or(|max| size <= max) && max_objects.is_none_or(|max| count <= max)
+            {
+                break;
+            }
+            let entry = &self.entries[hash];
+            if entry.dirty.is_none() {
+                size -= entry.size;
+                count -= 1;
+                evicted.push(*hash);
+            }
+        }
+        for hash in &evicted {
+            self.remove(*hash);
+        }
+        evicted
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cache: Mutex<Cache>,
+    loaded: async_lock::OnceCell<()>,
+    flushing: async_lock::Mutex<()>,
+    /// Held for reading while copying objects into the fast tier, and for writing while deleting
+    /// evicted ones from it, so that an object saved again isn't deleted after being saved.
+    evicting: async_lock::RwLock<()>,
+}
+
+fn is_missing(e: &object_rainbow::Error) -> bool {
+    match e {
+        object_rainbow::Error::HashNotFound => true,
+        object_rainbow::Error::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// [`RainbowStoreMut`] reading through a fast tier in front of a slow one.
+///
+/// Objects fetched from the slow tier are copied into the fast one with
+/// [`RainbowStore::save_raw`], which the fast tier has to support. Once the fast tier holds more
+/// than [`TieredStore::with_max_bytes`] or [`TieredStore::with_max_objects`], the least recently
+/// used objects are deleted from it. Only objects that went through this store count towards
+/// those limits. By default, there are none.
+///
+/// Refs live in the slow tier only. With [`WritePolicy::Back`], objects are flushed before any
+/// ref is set, so the slow tier never has a ref to an object it's missing. Until then, each one is
+/// marked with a ref of the fast tier, under `dirty/`, which is how a [`TieredStore`] opened over
+/// the same fast tier after a restart finds and flushes them.
+#[derive(Debug, Clone)]
+pub struct TieredStore<Fast, Slow> {
+    fast: Fast,
+    slow: Slow,
+    policy: WritePolicy,
+    max_bytes: Option<usize>,
+    max_objects: Option<usize>,
+    inner: Arc<Inner>,
+}
+
+impl<Fast: PartialEq, Slow: PartialEq> PartialEq for TieredStore<Fast, Slow> {
+    fn eq(&self, other: &Self) -> bool {
+        self.fast == other.fast && self.slow == other.slow && Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl<Fast, Slow> TieredStore<Fast, Slow> {
+    pub fn new(fast: Fast, slow: Slow) -> Self {
+        Self {
+            fast,
+            slow,
+            policy: WritePolicy::default(),
+            max_bytes: None,
+            max_objects: None,
+            inner: Default::default(),
+        }
+    }
+
+    pub fn with_write_policy(self, policy: WritePolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Keep at most `max_bytes` of object data in the fast tier.
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        Self {
+            max_bytes: Some(max_bytes),
+            ..self
+        }
+    }
+
+    /// Keep at most `max_objects` objects in the fast tier.
+    pub fn with_max_objects(self, max_objects: usize) -> Self {
+        Self {
+            max_objects: Some(max_objects),
+            ..self
+        }
+    }
+
+    pub fn fast(&self) -> &Fast {
+        &self.fast
+    }
+
+    pub fn slow(&self) -> &Slow {
+        &self.slow
+    }
+
+    fn cache<T>(&self, f: impl FnOnce(&mut Cache) -> T) -> T {
+        f(&mut self.inner.cache.lock().unwrap())
+    }
+}
+
+impl<Fast: RainbowStoreMut, Slow: RainbowStore> TieredStore<Fast, Slow> {
+    /// Pick up objects an earlier run left unflushed, so that they're flushed and not evicted.
+    async fn load(&self) -> object_rainbow::Result<()> {
+        self.inner
+            .loaded
+            .get_or_try_init(|| async {
+                let dirty = self.fast.list_refs(DIRTY).try_collect::<Vec<_>>().await?;
+                for (key, diff) in dirty {
+                    let (seq, hash) = parse_dirty_key(&key).ok_or_else(|| {
+                        object_rainbow::error_consistency!("invalid dirty ref {key:?}")
+                    })?;
+                    let size = match self.fast.fetch(hash).await {
+                        Ok(data) => data.as_ref().len(),
+                        Err(e) if is_missing(&e) => {
+                            self.fast.delete_ref(&key, None).await?;
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    self.cache(|cache| {
+                        cache.touch(hash, size);
+                        cache.mark(seq, hash, diff);
+                    });
+                }
+                Ok(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Copy `data` into the fast tier, then evict whatever doesn't fit anymore.
+    async fn populate(
+        &self,
+        hash: Hash,
+        data: &[u8],
+        diff: Option<Hash>,
+    ) -> object_rainbow::Result<()> {
+        self.load().await?;
+        {
+            let _populating = self.inner.evicting.read().await;
+            let dirty = match diff {
+                Some(diff) => {
+                    let seq = self.cache(|cache| {
+                        cache.seq += 1;
+                        cache.seq - 1
+                    });
+                    self.fast
+                        .update_ref(&dirty_key(seq, hash), None, diff)
+                        .await?;
+                    Some((seq, diff))
+                }
+                None => None,
+            };
+            self.fast.save_raw(hash, data).await?;
+            self.cache(|cache| {
+                cache.touch(hash, data.len());
+                if let Some((seq, diff)) = dirty {
+                    cache.mark(seq, hash, diff);
+                }
+            });
+        }
+        self.evict().await
+    }
+
+    async fn evict(&self) -> object_rainbow::Result<()> {
+        let _evicting = self.inner.evicting.write().await;
+        let evicted = self.cache(|cache| cache.evict(self.max_bytes, self.max_objects));
+        if !evicted.is_empty() {
+            self.fast.delete(&evicted).await?;
+        }
+        Ok(())
+    }
+
+    /// Write objects saved with [`WritePolicy::Back`] to the slow tier, in the order they were
+    /// saved in, including ones left unflushed by an earlier [`TieredStore`] over the same fast
+    /// tier.
+    pub async fn flush(&self) -> object_rainbow::Result<()> {
+        self.load().await?;
+        let _flushing = self.inner.flushing.lock().await;
+        let dirty = self.cache(|cache| cache.dirty.clone());
+        for (seq, (hash, diff)) in dirty {
+            match self.fast.fetch(hash).await {
+                Ok(data) => {
+                    let data = data.as_ref().to_vec();
+                    self.slow.save_data(WithHash { diff, data: &data }).await?;
+                }
+                // deleted since, or never saved in full
+                Err(e) if is_missing(&e) => {}
+                Err(e) => return Err(e),
+            }
+            self.fast.delete_ref(&dirty_key(seq, hash), None).await?;
+            self.cache(|cache| cache.clean(seq));
+        }
+        self.evict().await
+    }
+}
+
+impl<Fast: RainbowStoreMut, Slow: RainbowStore> RainbowStore for TieredStore<Fast, Slow> {
+    async fn save_data(
+        &self,
+        wh: WithHash<'_, impl Send + Sync + ToOutput>,
+    ) -> object_rainbow::Result<()> {
+        let hash = wh.data_hash();
+        if self.cache(|cache| cache.entries.contains_key(&hash)) {
+            return Ok(());
+        }
+        let data = wh.data.vec();
+        match self.policy {
+            WritePolicy::Through => {
+                self.slow.save_data(wh).await?;
+                self.populate(hash, &data, None).await
+            }
+            WritePolicy::Back => self.populate(hash, &data, Some(wh.diff)).await,
+        }
+    }
+
+    async fn contains(&self, hash: Hash) -> object_rainbow::Result<bool> {
+        Ok(self.fast.contains(hash).await? || self.slow.contains(hash).await?)
+    }
+
+    #[expect(refining_impl_trait)]
+    async fn fetch(&self, hash: Hash) -> object_rainbow::Result<Vec<u8>> {
+        self.load().await?;
+        match self.fast.fetch(hash).await {
+            Ok(data) => {
+                let data = data.as_ref().to_vec();
+                self.cache(|cache| cache.touch(hash, data.len()));
+                return Ok(data);
+            }
+            Err(e) if is_missing(&e) => {}
+            Err(e) => return Err(e),
+        }
+        let data = self.slow.fetch(hash).await?.as_ref().to_vec();
+        self.populate(hash, &data, None).await?;
+        Ok(data)
+    }
+
+    /// Objects of the slow tier. With [`WritePolicy::Back`], unflushed ones are left out.
+    fn list_objects(
+        &self,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(Hash, Option<SystemTime>)>> {
+        self.slow.list_objects()
+    }
+
+    async fn delete(&self, hashes: &[Hash]) -> object_rainbow::Result<()> {
+        self.load().await?;
+        let dirty = self.cache(|cache| {
+            hashes
+                .iter()
+                .filter_map(|hash| Some(dirty_key(cache.remove(*hash)?, *hash)))
+                .collect::<Vec<_>>()
+        });
+        self.fast.delete(hashes).await?;
+        for key in dirty {
+            self.fast.delete_ref(&key, None).await?;
+        }
+        self.slow.delete(hashes).await
+    }
+
+    /// Saving trees is bounded by the slow tier.
+    fn concurrency(&self) -> usize {
+        self.slow.concurrency()
+    }
+
+    /// Always written through, since the slow tier can't be given the diff later.
+    async fn save_raw(&self, hash: Hash, data: &[u8]) -> object_rainbow::Result<()> {
+        self.slow.save_raw(hash, data).await?;
+        self.populate(hash, data, None).await
+    }
+}
+
+impl<Fast: RainbowStoreMut, Slow: RainbowStoreMut> RainbowStoreMut for TieredStore<Fast, Slow> {
+    async fn create_ref(
+        &self,
+        hash: Hash,
+    ) -> object_rainbow::Result<impl 'static + Send + Sync + AsRef<str>> {
+        self.flush().await?;
+        self.slow.create_ref(hash).await
+    }
+
+    async fn update_ref(
+        &self,
+        key: &str,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> object_rainbow::Result<()> {
+        self.flush().await?;
+        self.slow.update_ref(key, old, hash).await
+    }
+
+    fn fetch_ref(&self, key: &str) -> impl RainbowFuture<T = OptionalHash> {
+        self.slow.fetch_ref(key)
+    }
+
+    fn ref_exists(&self, key: &str) -> impl RainbowFuture<T = bool> {
+        self.slow.ref_exists(key)
+    }
+
+    fn list_refs(
+        &self,
+        prefix: &str,
+    ) -> impl Send + Stream<Item = object_rainbow::Result<(String, Hash)>> {
+        self.slow.list_refs(prefix)
+    }
+
+    fn delete_ref(&self, key: &str, old: Option<OptionalHash>) -> impl RainbowFuture<T = ()> {
+        self.slow.delete_ref(key, old)
+    }
+
+    fn rename_ref(&self, from: &str, to: &str) -> impl RainbowFuture<T = ()> {
+        self.slow.rename_ref(from, to)
+    }
+
+    async fn update_refs(&self, updates: Vec<RefUpdate>) -> object_rainbow::Result<()> {
+        self.flush().await?;
+        self.slow.update_refs(updates).await
+    }
+
+    fn watch(&self, key: &str) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+        self.slow.watch(key)
+    }
+
+    fn reflog(&self, key: &str) -> impl RainbowFuture<T = Vec<ReflogEntry>> {
+        self.slow.reflog(key)
+    }
+
+    fn list_reflogs(&self) -> impl Send + Stream<Item = object_rainbow::Result<String>> {
+        self.slow.list_reflogs()
+    }
+
+    fn restore_ref(&self, key: &str, entry: &ReflogEntry) -> impl RainbowFuture<T = ()> {
+        self.slow.restore_ref(key, entry)
+    }
+}
@@ -0,0 +1,100 @@
+use object_rainbow::{Hash, OptionalHash};
+
+use crate::RainbowStoreMut;
+
+/// One update in a [`RefTransaction`], with the same meaning of `old` as in
+/// [`RainbowStoreMut::update_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub key: String,
+    pub old: Option<OptionalHash>,
+    pub hash: Hash,
+}
+
+impl RefUpdate {
+    /// Error for updates whose `old` didn't hold for `conflicts`, as returned by
+    /// [`RainbowStoreMut::update_refs`].
+    pub fn conflict_error(conflicts: &[impl AsRef<str>]) -> object_rainbow::Error {
+        object_rainbow::Error::consistency(RefConflict(
+            conflicts
+                .iter()
+                .map(|key| key.as_ref().to_owned())
+                .collect(),
+        ))
+    }
+
+    /// Error for an `old` that didn't hold for `key`, as returned by
+    /// [`RainbowStoreMut::update_ref`] and [`RainbowStoreMut::delete_ref`].
+    pub fn changed(key: &str) -> object_rainbow::Error {
+        Self::conflict_error(&[key])
+    }
+
+    /// Whether `error` came from [`Self::conflict_error`] or [`Self::changed`], meaning that
+    /// retrying with up-to-date `old`s may succeed, as opposed to other consistency errors.
+    pub fn is_conflict(error: &object_rainbow::Error) -> bool {
+        matches!(error, object_rainbow::Error::Consistency(e) if e.is::<RefConflict>())
+    }
+}
+
+/// Keys whose `old` didn't hold.
+#[derive(Debug)]
+struct RefConflict(Vec<String>);
+
+impl std::fmt::Display for RefConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.as_slice() {
+            [key] => write!(f, "ref {key:?} has been changed"),
+            keys => write!(f, "refs {keys:?} have been changed"),
+        }
+    }
+}
+
+impl std::error::Error for RefConflict {}
+
+/// Several ref updates, committed together through [`RainbowStoreMut::update_refs`].
+///
+/// How atomic that is depends on the store, see its documentation.
+#[derive(Debug, Clone)]
+pub struct RefTransaction<S> {
+    store: S,
+    updates: Vec<RefUpdate>,
+}
+
+impl<S: RainbowStoreMut> RefTransaction<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            updates: Vec::new(),
+        }
+    }
+
+    /// Set `key` to `hash`, if it's still at `old` when committing.
+    pub fn update(
+        &mut self,
+        key: impl Into<String>,
+        old: Option<OptionalHash>,
+        hash: Hash,
+    ) -> &mut Self {
+        self.updates.push(RefUpdate {
+            key: key.into(),
+            old,
+            hash,
+        });
+        self
+    }
+
+    pub fn updates(&self) -> &[RefUpdate] {
+        &self.updates
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    pub async fn commit(self) -> object_rainbow::Result<()> {
+        if self.updates.is_empty() {
+            return Ok(());
+        }
+        self.store.update_refs(self.updates).await
+    }
+}
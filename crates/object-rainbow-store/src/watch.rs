@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use async_io::Timer;
+use futures_util::Stream;
+use object_rainbow::OptionalHash;
+
+use crate::RainbowStoreMut;
+
+/// How often [`RainbowStoreMut::watch`] polls by default.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watch `key` by calling [`RainbowStoreMut::fetch_ref`] every `interval`, yielding its value
+/// whenever it differs from the previous one.
+pub fn poll_ref(
+    store: &impl RainbowStoreMut,
+    key: &str,
+    interval: Duration,
+) -> impl Send + Stream<Item = object_rainbow::Result<OptionalHash>> {
+    futures_util::stream::try_unfold(None, move |last| async move {
+        if last.is_some() {
+            Timer::after(interval).await;
+        }
+        loop {
+            let hash = store.fetch_ref(key).await?;
+            if last != Some(hash) {
+                return Ok(Some((hash, Some(hash))));
+            }
+            Timer::after(interval).await;
+        }
+    })
+}